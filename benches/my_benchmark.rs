@@ -3,9 +3,11 @@ extern crate criterion;
 
 use criterion::Criterion;
 
+use gitchain::backend;
+use gitchain::hasher::{Midstate, DIGEST_LEN};
 use gitchain::miner::Miner;
+use gitchain::target::Target;
 use gitchain::writer;
-use gitchain::*;
 
 // Benchmarking with six zeroes is extremely slow because criterion will run 5050 iterations which
 // at an average of 2 seconds per solve results in a very long benchmark time.  That's why the
@@ -17,30 +19,72 @@ fn benchmark_mining_six_zeroes(c: &mut Criterion) {
     let author = "AuthorTest <test@test.com>".to_string();
     let message = "MessageTest".to_string();
     let commit_time = time::strptime("2016-02-05 16:52:22", "%Y-%m-%d %H:%M:%S").unwrap();
-    let blob = writer::generate_blob(tree, parent, author, message, commit_time).unwrap();
+    let blob = writer::generate_blob(tree, parent, author.clone(), author, message, commit_time, commit_time).unwrap();
 
-    let mut miner = Miner::new("000000".to_string(), blob);
+    let mut miner = Miner::new(Target::prefix("000000").unwrap(), blob);
 
     c.bench_function("proof of work solving with 6 zeros prefix.", move |b| {
         b.iter(|| miner.solve().unwrap())
     });
 }
 
+#[allow(dead_code)]
 fn benchmark_mining_seven_zeroes(c: &mut Criterion) {
     let tree = "TreeTest".to_string();
     let parent = None;
     let author = "AuthorTest <test@test.com>".to_string();
     let message = "MessageTest".to_string();
     let commit_time = time::strptime("2016-02-05 16:52:22", "%Y-%m-%d %H:%M:%S").unwrap();
-    let blob = writer::generate_blob(tree, parent, author, message, commit_time).unwrap();
+    let blob = writer::generate_blob(tree, parent, author.clone(), author, message, commit_time, commit_time).unwrap();
 
-    let mut miner = Miner::new("0000000".to_string(), blob);
+    let mut miner = Miner::new(Target::prefix("0000000").unwrap(), blob);
 
     c.bench_function("proof of work solving with 7 zeros prefix.", move |b| {
         b.iter(|| miner.solve().unwrap())
     });
 }
 
-criterion_group!(benches, benchmark_mining_six_zeroes,);
+// The miner only rehashes the final block of the commit for each nonce, so a long message should
+// solve at the same rate as a short one.
+fn benchmark_mining_five_zeroes_long_message(c: &mut Criterion) {
+    let tree = "TreeTest".to_string();
+    let parent = None;
+    let author = "AuthorTest <test@test.com>".to_string();
+    let message = "MessageTest ".repeat(1000);
+    let commit_time = time::strptime("2016-02-05 16:52:22", "%Y-%m-%d %H:%M:%S").unwrap();
+    let blob = writer::generate_blob(tree, parent, author.clone(), author, message, commit_time, commit_time).unwrap();
+
+    let mut miner = Miner::new(Target::prefix("00000").unwrap(), blob);
+
+    c.bench_function("proof of work solving with 5 zeros prefix and a long message.", move |b| {
+        b.iter(|| miner.solve().unwrap())
+    });
+}
+
+// Hashes the same 1024 candidates with every backend this machine supports, so they can be compared
+// side by side without waiting on a full solve.
+fn benchmark_backends(c: &mut Criterion) {
+    for backend in backend::all().into_iter().filter(|backend| backend.is_available()) {
+        let midstate = Midstate::new(&[b'x'; 100], writer::NONCE_DIGITS, &[]);
+        let buffers = vec![midstate.tail_buffer(); backend.lanes()];
+        let mut digests = vec![[0u8; DIGEST_LEN]; backend.lanes()];
+        let name = format!("hashing 1024 candidates with the {} backend.", backend.name());
+
+        c.bench_function(&name, move |b| {
+            b.iter(|| {
+                for _ in 0..1024 / backend.lanes() {
+                    midstate.finish(backend.as_ref(), &buffers, &mut digests);
+                }
+            })
+        });
+    }
+}
+
+criterion_group!(
+    benches,
+    benchmark_mining_six_zeroes,
+    benchmark_mining_five_zeroes_long_message,
+    benchmark_backends,
+);
 
 criterion_main!(benches);
@@ -0,0 +1,310 @@
+use crate::errors::BackendError;
+use crate::hasher::DIGEST_LEN;
+use failure::Error;
+use std::sync::{Arc, OnceLock, RwLock};
+
+#[cfg(target_arch = "x86_64")]
+mod avx2;
+mod scalar;
+#[cfg(target_arch = "x86_64")]
+mod shani;
+
+#[cfg(target_arch = "x86_64")]
+pub use self::avx2::Avx2;
+pub use self::scalar::Scalar;
+#[cfg(target_arch = "x86_64")]
+pub use self::shani::ShaNi;
+
+/// SHA-1's initial state, before any blocks have been compressed.
+pub const SHA1_INIT: [u32; 5] = [0x6745_2301, 0xefcd_ab89, 0x98ba_dcfe, 0x1032_5476, 0xc3d2_e1f0];
+
+/// Length in bytes of a SHA-1 block.
+pub const BLOCK_LEN: usize = 64;
+
+const KNOWN_ANSWERS: [(&[u8], &str); 3] = [
+    (b"", "da39a3ee5e6b4b0d3255bfef95601890afd80709"),
+    (b"abc", "a9993e364706816aba3e25717850c26c9cd0d89d"),
+    (
+        b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq",
+        "84983e441c3bd26ebaae4aa1f95129e5e54670f1",
+    ),
+];
+
+/// A SHA-1 implementation the miner can hash git objects and candidate commits with.  Only
+/// `name` and `compress` have to be provided; the rest are built on top of them, but can be
+/// overridden by implementations that can do better than one block at a time.
+pub trait HashBackend: Send + Sync {
+    /// The name used to pick this backend, for example from the command line.
+    fn name(&self) -> &str;
+
+    /// Whether this backend can run on the current machine.
+    fn is_available(&self) -> bool {
+        true
+    }
+
+    /// How strongly `detect` prefers this backend over the others: the higher, the sooner it is
+    /// tried.  The backends shipped with gitchain go from 0 for scalar up to 20 for SHA-NI.
+    fn priority(&self) -> i32 {
+        0
+    }
+
+    /// Number of candidates hashed per call to `compress` or `hash_candidates`.
+    fn lanes(&self) -> usize {
+        1
+    }
+
+    /// Compresses `blocks[lane]`, a 64 byte block, into `states[lane]` for every lane.  Both
+    /// slices hold exactly `lanes()` entries.
+    fn compress(&self, states: &mut [[u32; 5]], blocks: &[&[u8]]);
+
+    /// Hashes a complete git object, `commit <len>\0` header included, and returns its digest.
+    fn hash_object(&self, object: &[u8]) -> [u8; DIGEST_LEN] {
+        let padded = sha1_pad(object);
+        let mut states = vec![SHA1_INIT; self.lanes()];
+
+        for block in padded.chunks(BLOCK_LEN) {
+            let blocks = vec![block; self.lanes()];
+            self.compress(&mut states, &blocks);
+        }
+
+        state_to_digest(&states[0])
+    }
+
+    /// Finishes one candidate per lane, starting from a shared midstate and compressing that
+    /// candidate's padded tail, and stores the raw digests in `digests`.
+    fn hash_candidates(&self, midstate: &[u32; 5], tails: &[Vec<u8>], digests: &mut [[u8; DIGEST_LEN]]) {
+        let mut states = vec![*midstate; self.lanes()];
+        let mut blocks: Vec<&[u8]> = vec![&[]; self.lanes()];
+
+        for offset in (0..tails[0].len()).step_by(BLOCK_LEN) {
+            for (block, tail) in blocks.iter_mut().zip(tails.iter()) {
+                *block = &tail[offset..offset + BLOCK_LEN];
+            }
+            self.compress(&mut states, &blocks);
+        }
+
+        for (digest, state) in digests.iter_mut().zip(states.iter()) {
+            *digest = state_to_digest(state);
+        }
+    }
+
+    /// Runs known-answer tests, covering both whole objects and every candidate lane.  Returns
+    /// false if the backend is unavailable or gets any of them wrong.
+    fn self_test(&self) -> bool {
+        if !self.is_available() {
+            return false;
+        }
+
+        let objects_pass = KNOWN_ANSWERS.iter()
+            .all(|(message, expected)| to_hex(&self.hash_object(message)) == *expected);
+
+        let front: Vec<u8> = (0..100u8).collect();
+        let tails: Vec<Vec<u8>> = (0..self.lanes())
+            .map(|lane| {
+                let mut message = front.clone();
+                message.extend((0..8).map(|i| (lane * 8 + i) as u8));
+                sha1_pad(&message)[BLOCK_LEN..].to_vec()
+            })
+            .collect();
+
+        let mut midstate = SHA1_INIT;
+        Scalar.compress(std::slice::from_mut(&mut midstate), &[&front[..BLOCK_LEN]]);
+        let mut digests = vec![[0u8; DIGEST_LEN]; self.lanes()];
+        self.hash_candidates(&midstate, &tails, &mut digests);
+
+        let candidates_pass = digests.iter().enumerate().all(|(lane, digest)| {
+            let mut message = front.clone();
+            message.extend((0..8).map(|i| (lane * 8 + i) as u8));
+            *digest == Scalar.hash_object(&message)
+        });
+
+        objects_pass && candidates_pass
+    }
+}
+
+/// A set of backends that can be picked by name, ranked by priority.
+#[derive(Clone)]
+pub struct Registry {
+    backends: Vec<Arc<dyn HashBackend>>,
+}
+
+impl Registry {
+    /// A registry of the backends shipped with gitchain.
+    pub fn builtin() -> Registry {
+        let mut registry = Registry { backends: Vec::new() };
+        #[cfg(target_arch = "x86_64")]
+        {
+            registry.register(Arc::new(ShaNi));
+            registry.register(Arc::new(Avx2));
+        }
+        registry.register(Arc::new(Scalar));
+        registry
+    }
+
+    /// Adds a backend, ranked by its priority and ahead of any other of the same priority.  A
+    /// backend with the same name as an existing one replaces it.
+    pub fn register(&mut self, backend: Arc<dyn HashBackend>) {
+        self.backends.retain(|existing| existing.name() != backend.name());
+        let index = self.backends.partition_point(|existing| existing.priority() > backend.priority());
+        self.backends.insert(index, backend);
+    }
+
+    /// Every backend, in the order `detect` prefers them, whether or not they can run on this
+    /// machine.
+    pub fn all(&self) -> Vec<Arc<dyn HashBackend>> {
+        self.backends.clone()
+    }
+
+    /// Looks up a backend by name.
+    pub fn find(&self, name: &str) -> Result<Arc<dyn HashBackend>, Error> {
+        let backend = self.backends.iter()
+            .find(|backend| backend.name() == name)
+            .cloned()
+            .ok_or(BackendError::UnknownBackendError { name: name.to_string() })?;
+
+        Ok(backend)
+    }
+
+    /// Picks the most preferred backend which can run on this machine and passes its self-test,
+    /// falling back to the portable scalar backend.
+    pub fn detect(&self) -> Arc<dyn HashBackend> {
+        self.backends.iter()
+            .find(|backend| backend.is_available() && backend.self_test())
+            .cloned()
+            .unwrap_or_else(|| Arc::new(Scalar))
+    }
+
+    /// Resolves the backend to mine with.  A backend requested by name must be able to run on
+    /// this machine and pass its self-test, otherwise the best detected backend is used.
+    pub fn select(&self, name: Option<&str>) -> Result<Arc<dyn HashBackend>, Error> {
+        let backend = match name {
+            Some(name) => self.find(name)?,
+            None => return Ok(self.detect()),
+        };
+
+        if !backend.is_available() {
+            return Err(BackendError::UnavailableError { name: backend.name().to_string() }.into());
+        }
+        if !backend.self_test() {
+            return Err(BackendError::SelfTestError { name: backend.name().to_string() }.into());
+        }
+
+        Ok(backend)
+    }
+}
+
+fn registry() -> &'static RwLock<Registry> {
+    static REGISTRY: OnceLock<RwLock<Registry>> = OnceLock::new();
+
+    REGISTRY.get_or_init(|| RwLock::new(Registry::builtin()))
+}
+
+/// Registers a backend with gitchain so it can be picked by name, and so `detect` prefers it over
+/// the shipped backends of no higher priority.  A backend with the same name as an existing one
+/// replaces it.
+pub fn register(backend: Arc<dyn HashBackend>) {
+    registry().write().unwrap().register(backend);
+}
+
+/// Every registered backend, in the order `detect` prefers them, whether or not they can run on
+/// this machine.
+pub fn all() -> Vec<Arc<dyn HashBackend>> {
+    registry().read().unwrap().all()
+}
+
+/// Looks up a registered backend by name.
+pub fn find(name: &str) -> Result<Arc<dyn HashBackend>, Error> {
+    registry().read().unwrap().find(name)
+}
+
+/// Picks the most preferred registered backend which can run on this machine and passes its
+/// self-test, falling back to the portable scalar backend.
+pub fn detect() -> Arc<dyn HashBackend> {
+    registry().read().unwrap().detect()
+}
+
+/// Resolves the backend to mine with from the registered ones.  A backend requested by name must
+/// be able to run on this machine and pass its self-test, otherwise the best detected backend is
+/// used.
+pub fn select(name: Option<&str>) -> Result<Arc<dyn HashBackend>, Error> {
+    registry().read().unwrap().select(name)
+}
+
+fn sha1_pad(message: &[u8]) -> Vec<u8> {
+    let mut padded = message.to_vec();
+    padded.push(0x80);
+    while padded.len() % BLOCK_LEN != 56 {
+        padded.push(0);
+    }
+    padded.extend_from_slice(&((message.len() as u64) * 8).to_be_bytes());
+    padded
+}
+
+fn state_to_digest(state: &[u32; 5]) -> [u8; DIGEST_LEN] {
+    let mut digest = [0u8; DIGEST_LEN];
+    for (bytes, word) in digest.chunks_mut(4).zip(state.iter()) {
+        bytes.copy_from_slice(&word.to_be_bytes());
+    }
+    digest
+}
+
+fn to_hex(digest: &[u8]) -> String {
+    digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Reversed;
+
+    impl HashBackend for Reversed {
+        fn name(&self) -> &str {
+            "test-reversed"
+        }
+
+        fn lanes(&self) -> usize {
+            3
+        }
+
+        fn compress(&self, states: &mut [[u32; 5]], blocks: &[&[u8]]) {
+            for (state, block) in states.iter_mut().zip(blocks.iter()).rev() {
+                Scalar.compress(std::slice::from_mut(state), &[block]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_every_available_backend_passes_self_test() {
+        for backend in all().iter().filter(|backend| backend.is_available()) {
+            assert!(backend.self_test(), "{} failed its self-test", backend.name());
+        }
+    }
+
+    #[test]
+    fn test_scalar_backend_is_always_available() {
+        assert_eq!(select(Some("scalar")).unwrap().name(), "scalar");
+        assert!(select(Some("sha3")).is_err());
+    }
+
+    #[test]
+    fn test_registered_backend_can_be_selected() {
+        let mut registry = Registry::builtin();
+        registry.register(Arc::new(Reversed));
+        let backend = registry.select(Some("test-reversed")).unwrap();
+        assert_eq!(backend.lanes(), 3);
+        assert!(backend.self_test());
+        assert!(find("test-reversed").is_err());
+    }
+
+    #[test]
+    fn test_backends_are_ranked_by_priority() {
+        let mut registry = Registry::builtin();
+        registry.register(Arc::new(Reversed));
+        let names: Vec<String> = registry.all().iter().map(|backend| backend.name().to_string()).collect();
+        #[cfg(target_arch = "x86_64")]
+        assert_eq!(names, vec!["sha-ni", "avx2", "test-reversed", "scalar"]);
+        #[cfg(not(target_arch = "x86_64"))]
+        assert_eq!(names, vec!["test-reversed", "scalar"]);
+    }
+}
@@ -0,0 +1,111 @@
+//! SHA-1 compression of eight independent blocks at once, one per 32 bit lane of an AVX2
+//! register.
+
+use super::HashBackend;
+use std::arch::x86_64::*;
+
+/// Number of blocks compressed per call.
+const LANES: usize = 8;
+
+/// Backend built on an AVX2 multi-buffer kernel, hashing eight candidates at a time.
+pub struct Avx2;
+
+impl HashBackend for Avx2 {
+    fn name(&self) -> &str {
+        "avx2"
+    }
+
+    fn priority(&self) -> i32 {
+        10
+    }
+
+    fn is_available(&self) -> bool {
+        is_x86_feature_detected!("avx2")
+    }
+
+    fn lanes(&self) -> usize {
+        LANES
+    }
+
+    fn compress(&self, states: &mut [[u32; 5]], blocks: &[&[u8]]) {
+        assert!(self.is_available());
+        unsafe { compress(states, blocks) }
+    }
+}
+
+const K: [u32; 4] = [0x5a82_7999, 0x6ed9_eba1, 0x8f1b_bcdc, 0xca62_c1d6];
+
+macro_rules! rotl {
+    ($x:expr, $n:expr) => {
+        _mm256_or_si256(_mm256_slli_epi32($x, $n), _mm256_srli_epi32($x, 32 - $n))
+    };
+}
+
+/// Compresses `blocks[lane]` into `states[lane]` for each of the eight lanes.
+///
+/// # Safety
+///
+/// The CPU must support the `avx2` feature.
+#[target_feature(enable = "avx2")]
+unsafe fn compress(states: &mut [[u32; 5]], blocks: &[&[u8]]) {
+    assert_eq!(states.len(), LANES);
+    assert_eq!(blocks.len(), LANES);
+
+    let mut lanes = [0u32; LANES];
+
+    let mut w = [_mm256_setzero_si256(); 16];
+    for (i, word) in w.iter_mut().enumerate() {
+        for (slot, block) in lanes.iter_mut().zip(blocks.iter()) {
+            let bytes = &block[i * 4..i * 4 + 4];
+            *slot = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+        *word = _mm256_loadu_si256(lanes.as_ptr() as *const __m256i);
+    }
+
+    let mut initial = [_mm256_setzero_si256(); 5];
+    for (i, register) in initial.iter_mut().enumerate() {
+        for (slot, state) in lanes.iter_mut().zip(states.iter()) {
+            *slot = state[i];
+        }
+        *register = _mm256_loadu_si256(lanes.as_ptr() as *const __m256i);
+    }
+
+    let [mut a, mut b, mut c, mut d, mut e] = initial;
+    for t in 0..80 {
+        if t >= 16 {
+            let mixed = _mm256_xor_si256(
+                _mm256_xor_si256(w[(t + 13) % 16], w[(t + 8) % 16]),
+                _mm256_xor_si256(w[(t + 2) % 16], w[t % 16]),
+            );
+            w[t % 16] = rotl!(mixed, 1);
+        }
+
+        let f = match t / 20 {
+            0 => _mm256_or_si256(_mm256_and_si256(b, c), _mm256_andnot_si256(b, d)),
+            2 => _mm256_or_si256(
+                _mm256_and_si256(b, c),
+                _mm256_and_si256(d, _mm256_or_si256(b, c)),
+            ),
+            _ => _mm256_xor_si256(_mm256_xor_si256(b, c), d),
+        };
+        let k = _mm256_set1_epi32(K[t / 20] as i32);
+
+        let temp = _mm256_add_epi32(
+            _mm256_add_epi32(rotl!(a, 5), f),
+            _mm256_add_epi32(_mm256_add_epi32(e, k), w[t % 16]),
+        );
+        e = d;
+        d = c;
+        c = rotl!(b, 30);
+        b = a;
+        a = temp;
+    }
+
+    for (i, register) in [a, b, c, d, e].iter().enumerate() {
+        let sum = _mm256_add_epi32(initial[i], *register);
+        _mm256_storeu_si256(lanes.as_mut_ptr() as *mut __m256i, sum);
+        for (state, word) in states.iter_mut().zip(lanes.iter()) {
+            state[i] = *word;
+        }
+    }
+}
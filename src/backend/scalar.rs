@@ -0,0 +1,15 @@
+use super::HashBackend;
+use crypto::sha1::sha1_digest_block;
+
+/// Portable backend built on rust-crypto's SHA-1, available on every machine.
+pub struct Scalar;
+
+impl HashBackend for Scalar {
+    fn name(&self) -> &str {
+        "scalar"
+    }
+
+    fn compress(&self, states: &mut [[u32; 5]], blocks: &[&[u8]]) {
+        sha1_digest_block(&mut states[0], blocks[0]);
+    }
+}
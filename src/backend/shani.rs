@@ -0,0 +1,106 @@
+//! SHA-1 compression using the x86 SHA extensions, four rounds per instruction.
+
+use super::HashBackend;
+use std::arch::x86_64::*;
+
+/// Backend built on the Intel SHA extensions, hashing one candidate at a time.
+pub struct ShaNi;
+
+impl HashBackend for ShaNi {
+    fn name(&self) -> &str {
+        "sha-ni"
+    }
+
+    fn priority(&self) -> i32 {
+        20
+    }
+
+    fn is_available(&self) -> bool {
+        is_x86_feature_detected!("sha")
+            && is_x86_feature_detected!("ssse3")
+            && is_x86_feature_detected!("sse4.1")
+    }
+
+    fn compress(&self, states: &mut [[u32; 5]], blocks: &[&[u8]]) {
+        assert!(self.is_available());
+        unsafe { compress(&mut states[0], blocks[0]) }
+    }
+}
+
+macro_rules! rounds4 {
+    ($h0:ident, $h1:ident, $wk:expr, $i:expr) => {
+        _mm_sha1rnds4_epu32($h0, _mm_sha1nexte_epu32($h1, $wk), $i)
+    };
+}
+
+macro_rules! schedule {
+    ($v0:expr, $v1:expr, $v2:expr, $v3:expr) => {
+        _mm_sha1msg2_epu32(_mm_xor_si128(_mm_sha1msg1_epu32($v0, $v1), $v2), $v3)
+    };
+}
+
+macro_rules! schedule_rounds4 {
+    ($h0:ident, $h1:ident, $w0:expr, $w1:expr, $w2:expr, $w3:expr, $w4:expr, $i:expr) => {
+        $w4 = schedule!($w0, $w1, $w2, $w3);
+        $h1 = rounds4!($h0, $h1, $w4, $i);
+    };
+}
+
+/// Compresses a single 64 byte block into `state`.
+///
+/// # Safety
+///
+/// The CPU must support the `sha`, `ssse3` and `sse4.1` features.
+#[target_feature(enable = "sha,sse2,ssse3,sse4.1")]
+unsafe fn compress(state: &mut [u32; 5], block: &[u8]) {
+    assert_eq!(block.len(), 64);
+
+    let mask = _mm_set_epi64x(0x0001_0203_0405_0607, 0x0809_0a0b_0c0d_0e0f);
+    let state_abcd = _mm_set_epi32(state[0] as i32, state[1] as i32, state[2] as i32, state[3] as i32);
+    let state_e = _mm_set_epi32(state[4] as i32, 0, 0, 0);
+
+    let words = block.as_ptr() as *const __m128i;
+    let mut w0 = _mm_shuffle_epi8(_mm_loadu_si128(words), mask);
+    let mut w1 = _mm_shuffle_epi8(_mm_loadu_si128(words.add(1)), mask);
+    let mut w2 = _mm_shuffle_epi8(_mm_loadu_si128(words.add(2)), mask);
+    let mut w3 = _mm_shuffle_epi8(_mm_loadu_si128(words.add(3)), mask);
+    let mut w4;
+
+    // Rounds 0..20
+    let mut h0 = state_abcd;
+    let mut h1 = _mm_sha1rnds4_epu32(h0, _mm_add_epi32(state_e, w0), 0);
+    h0 = rounds4!(h1, h0, w1, 0);
+    h1 = rounds4!(h0, h1, w2, 0);
+    h0 = rounds4!(h1, h0, w3, 0);
+    schedule_rounds4!(h0, h1, w0, w1, w2, w3, w4, 0);
+
+    // Rounds 20..40
+    schedule_rounds4!(h1, h0, w1, w2, w3, w4, w0, 1);
+    schedule_rounds4!(h0, h1, w2, w3, w4, w0, w1, 1);
+    schedule_rounds4!(h1, h0, w3, w4, w0, w1, w2, 1);
+    schedule_rounds4!(h0, h1, w4, w0, w1, w2, w3, 1);
+    schedule_rounds4!(h1, h0, w0, w1, w2, w3, w4, 1);
+
+    // Rounds 40..60
+    schedule_rounds4!(h0, h1, w1, w2, w3, w4, w0, 2);
+    schedule_rounds4!(h1, h0, w2, w3, w4, w0, w1, 2);
+    schedule_rounds4!(h0, h1, w3, w4, w0, w1, w2, 2);
+    schedule_rounds4!(h1, h0, w4, w0, w1, w2, w3, 2);
+    schedule_rounds4!(h0, h1, w0, w1, w2, w3, w4, 2);
+
+    // Rounds 60..80
+    schedule_rounds4!(h1, h0, w1, w2, w3, w4, w0, 3);
+    schedule_rounds4!(h0, h1, w2, w3, w4, w0, w1, 3);
+    schedule_rounds4!(h1, h0, w3, w4, w0, w1, w2, 3);
+    schedule_rounds4!(h0, h1, w4, w0, w1, w2, w3, 3);
+    schedule_rounds4!(h1, h0, w0, w1, w2, w3, w4, 3);
+
+    let abcd = _mm_add_epi32(state_abcd, h0);
+    let e = _mm_sha1nexte_epu32(h1, state_e);
+
+    state[0] = _mm_extract_epi32(abcd, 3) as u32;
+    state[1] = _mm_extract_epi32(abcd, 2) as u32;
+    state[2] = _mm_extract_epi32(abcd, 1) as u32;
+    state[3] = _mm_extract_epi32(abcd, 0) as u32;
+    state[4] = _mm_extract_epi32(e, 3) as u32;
+}
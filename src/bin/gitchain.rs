@@ -12,6 +12,6 @@ fn main() {
 
         writeln!(stderr, "error: {}", e).expect(errmsg);
 
-        process::exit(1);
+        process::exit(gitchain::cancel::exit_code(e));
     };
 }
@@ -0,0 +1,183 @@
+use crate::backend::HashBackend;
+use crate::cancel::CancellationToken;
+use crate::errors::CalibrationError;
+use crate::miner::Miner;
+use crate::progress::{MiningObserver, Progress};
+use crate::target::Target;
+use crate::workers::Workers;
+use failure::Error;
+use serde_derive::{Deserialize, Serialize};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// How long `gitchain bench` mines for with each backend and thread count.
+pub const MEASURE_TIME: Duration = Duration::from_millis(500);
+
+/// The hashrate measured for one backend with one number of threads.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Measurement {
+    pub backend: String,
+    pub threads: usize,
+    pub hashrate: f64,
+}
+
+/// Hashrates measured on this machine by `gitchain bench`, saved so later commits can say how
+/// long they are likely to take before they start.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Calibration {
+    pub measurements: Vec<Measurement>,
+}
+
+impl Calibration {
+    /// Where the calibration is saved: `gitchain/calibration.json` in `$XDG_CONFIG_HOME`, or in
+    /// `~/.config` if that isn't set.
+    pub fn default_path() -> Result<PathBuf, Error> {
+        let config = env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+            .ok_or(CalibrationError::NoConfigDirError {})?;
+
+        Ok(config.join("gitchain").join("calibration.json"))
+    }
+
+    /// Reads the calibration saved at `path`, or returns `None` if nothing has been saved there.
+    pub fn load(path: &Path) -> Result<Option<Calibration>, Error> {
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let invalid = || CalibrationError::ReadError { path: path.display().to_string() };
+        let contents = fs::read_to_string(path).map_err(|_| invalid())?;
+        let calibration = serde_json::from_str(&contents).map_err(|_| invalid())?;
+
+        Ok(Some(calibration))
+    }
+
+    /// Saves the calibration to `path`, creating its directory if need be.
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        let failed = || CalibrationError::WriteError { path: path.display().to_string() };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|_| failed())?;
+        }
+
+        let contents = serde_json::to_string_pretty(self).map_err(|_| failed())?;
+        fs::write(path, contents).map_err(|_| failed())?;
+
+        Ok(())
+    }
+
+    /// The hashrate to expect from `backend` with `threads` threads.  Without a measurement for
+    /// exactly that many threads, the closest one for the backend is scaled to fit.
+    pub fn hashrate(&self, backend: &str, threads: usize) -> Option<f64> {
+        self.measurements.iter()
+            .filter(|measurement| measurement.backend == backend && measurement.threads > 0)
+            .min_by_key(|measurement| (measurement.threads as i64 - threads as i64).abs())
+            .map(|measurement| measurement.hashrate * threads as f64 / measurement.threads as f64)
+    }
+
+    /// The fastest measurement.
+    pub fn best(&self) -> Option<&Measurement> {
+        self.measurements.iter().max_by(|a, b| a.hashrate.total_cmp(&b.hashrate))
+    }
+}
+
+/// The thread counts `gitchain bench` measures: powers of two below `max`, then `max` itself.
+pub fn thread_counts(max: usize) -> Vec<usize> {
+    let mut counts: Vec<usize> = (0..).map(|power| 1 << power).take_while(|&threads| threads < max).collect();
+    counts.push(max.max(1));
+    counts
+}
+
+/// Measures the hashrate of `backend` with `threads` threads, by mining for an impossible hash
+/// for `duration` with the same code a real commit is mined with.
+pub fn measure(backend: Arc<dyn HashBackend>, threads: usize, duration: Duration) -> Result<Measurement, Error> {
+    let recorder = Arc::new(LastProgress::default());
+    let name = backend.name().to_string();
+    let blob = "tree 4b825dc642cb6eb9a060e54bf8d69288fbee4904\n\n\
+                gitchain bench".to_string();
+
+    let mut miner = Miner::new(Target::prefix(&"0".repeat(40))?, blob)
+        .with_backend(backend)
+        .with_thread_pool(Workers::new().with_threads(threads).build_pool()?)
+        .with_observer(recorder.clone());
+    let _ = miner.solve_cancellable(&CancellationToken::new().with_timeout(duration));
+
+    let hashrate = recorder.0.lock()
+        .ok()
+        .and_then(|progress| progress.as_ref().map(Progress::hashrate))
+        .unwrap_or(0.0);
+
+    Ok(Measurement { backend: name, threads, hashrate })
+}
+
+#[derive(Default)]
+struct LastProgress(Mutex<Option<Progress>>);
+
+impl MiningObserver for LastProgress {
+    fn on_progress(&self, _progress: &Progress) {}
+
+    fn on_finish(&self, progress: &Progress) {
+        if let Ok(mut last) = self.0.lock() {
+            *last = Some(progress.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend;
+    use tempfile::TempDir;
+
+    fn measurement(backend: &str, threads: usize, hashrate: f64) -> Measurement {
+        Measurement { backend: backend.to_string(), threads, hashrate }
+    }
+
+    #[test]
+    fn test_thread_counts() {
+        assert_eq!(thread_counts(1), vec![1]);
+        assert_eq!(thread_counts(4), vec![1, 2, 4]);
+        assert_eq!(thread_counts(6), vec![1, 2, 4, 6]);
+    }
+
+    #[test]
+    fn test_hashrate_lookup() {
+        let calibration = Calibration {
+            measurements: vec![
+                measurement("scalar", 1, 5e6),
+                measurement("scalar", 4, 18e6),
+                measurement("sha-ni", 1, 14e6),
+            ],
+        };
+
+        assert_eq!(calibration.hashrate("scalar", 4), Some(18e6));
+        assert_eq!(calibration.hashrate("scalar", 8), Some(36e6));
+        assert_eq!(calibration.hashrate("sha-ni", 2), Some(28e6));
+        assert_eq!(calibration.hashrate("avx2", 1), None);
+        assert_eq!(calibration.best(), Some(&measurement("scalar", 4, 18e6)));
+    }
+
+    #[test]
+    fn test_calibration_round_trip() -> Result<(), Error> {
+        let dir = TempDir::new()?;
+        let path = dir.path().join("gitchain").join("calibration.json");
+        assert!(Calibration::load(&path)?.is_none());
+
+        let calibration = Calibration { measurements: vec![measurement("scalar", 2, 9e6)] };
+        calibration.save(&path)?;
+        assert_eq!(Calibration::load(&path)?.unwrap().measurements, calibration.measurements);
+        Ok(())
+    }
+
+    #[test]
+    fn test_measuring_a_backend() -> Result<(), Error> {
+        let measurement = measure(backend::find("scalar")?, 1, Duration::from_millis(100))?;
+        assert_eq!(measurement.backend, "scalar");
+        assert_eq!(measurement.threads, 1);
+        assert!(measurement.hashrate > 0.0);
+        Ok(())
+    }
+}
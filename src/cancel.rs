@@ -0,0 +1,108 @@
+use crate::errors::MiningError;
+use failure::Error;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Exit code used when mining was cancelled, the same one a shell reports for a process stopped
+/// by Ctrl-C.
+pub const EXIT_CANCELLED: i32 = 130;
+/// Exit code used when mining ran out of time, the same one `timeout(1)` uses.
+pub const EXIT_TIMED_OUT: i32 = 124;
+
+/// Tells a miner to stop.  Clones share the same flag, so one can be handed to a signal handler or
+/// another thread and cancelled from there.  A token can also carry a deadline, after which it
+/// counts as cancelled on its own.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+    deadline: Option<Instant>,
+}
+
+impl CancellationToken {
+    /// Creates a token that is only cancelled when `cancel` is called.
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    /// Gives the token a deadline `timeout` from now.  Clones made before this keep their old one.
+    /// A timeout too far off for the clock to reach sets no deadline.
+    pub fn with_timeout(mut self, timeout: Duration) -> CancellationToken {
+        if let Some(deadline) = Instant::now().checked_add(timeout) {
+            self.deadline = Some(deadline);
+        }
+        self
+    }
+
+    /// Asks every miner holding this token, or a clone of it, to stop.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether `cancel` has been called or the deadline has passed.
+    pub fn is_cancelled(&self) -> bool {
+        self.check().is_err()
+    }
+
+    /// Returns the error a miner stops with if the token is cancelled.
+    pub fn check(&self) -> Result<(), Error> {
+        if self.cancelled.load(Ordering::Relaxed) {
+            return Err(MiningError::CancelledError {}.into());
+        }
+        if self.deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            return Err(MiningError::TimedOutError {}.into());
+        }
+
+        Ok(())
+    }
+
+    /// Cancels the token when the process receives SIGINT, so Ctrl-C stops the miner cleanly
+    /// instead of killing the process.  A second Ctrl-C exits straight away.
+    #[cfg(unix)]
+    pub fn cancel_on_ctrl_c(&self) -> Result<(), Error> {
+        use signal_hook::consts::SIGINT;
+        use signal_hook::flag;
+
+        flag::register_conditional_shutdown(SIGINT, EXIT_CANCELLED, self.cancelled.clone())?;
+        flag::register(SIGINT, self.cancelled.clone())?;
+        Ok(())
+    }
+}
+
+/// The exit code the command line should use for an error: one of the codes above if mining was
+/// aborted, or 1 if anything actually failed.
+pub fn exit_code(error: &Error) -> i32 {
+    match error.downcast_ref::<MiningError>() {
+        Some(MiningError::CancelledError {}) => EXIT_CANCELLED,
+        Some(MiningError::TimedOutError {}) => EXIT_TIMED_OUT,
+        _ => 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clones_share_cancellation() {
+        let token = CancellationToken::new();
+        let clone = token.clone();
+        assert!(!token.is_cancelled());
+
+        clone.cancel();
+        assert!(token.is_cancelled());
+        assert_eq!(exit_code(&token.check().unwrap_err()), EXIT_CANCELLED);
+    }
+
+    #[test]
+    fn test_deadline_times_out() {
+        let token = CancellationToken::new().with_timeout(Duration::from_secs(0));
+        assert_eq!(exit_code(&token.check().unwrap_err()), EXIT_TIMED_OUT);
+
+        let token = CancellationToken::new().with_timeout(Duration::from_secs(3600));
+        assert!(token.check().is_ok());
+
+        let token = CancellationToken::new().with_timeout(Duration::from_secs(u64::MAX));
+        assert!(token.check().is_ok());
+    }
+}
@@ -0,0 +1,336 @@
+use crate::errors::CheckpointError;
+use crate::hasher;
+use crate::writer;
+use failure::Error;
+use serde_derive::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Directory inside `.git` that checkpoints are kept in.
+pub const CHECKPOINT_DIR: &str = "gitchain";
+
+/// How often a journal writes the ranges searched so far to disk.
+pub const SAVE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// The two kinds of candidates a miner searches, whose progress is tracked separately.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Space {
+    /// Combinations of commit timestamps within a jitter window.
+    Timestamps,
+    /// Nonces, including the extra nonce in their high 32 bits.
+    Nonces,
+}
+
+/// Everything needed to pick a search back up: what was being mined, which candidates have been
+/// tried, and the solution once there is one.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// The commit before a nonce is added to it, timestamps and all.
+    pub blob: String,
+    /// The description of the predicate being mined for.
+    pub target: String,
+    /// The name of the nonce encoding in use.
+    pub nonce_style: String,
+    /// The jitter window in use, if any.
+    pub jitter: Option<String>,
+    /// Ranges of timestamp combinations already tried.
+    pub timestamps: Ranges,
+    /// Ranges of nonces already tried.
+    pub nonces: Ranges,
+    /// The mined commit and its hash, once found.
+    pub solution: Option<Solution>,
+}
+
+/// A mined commit, saved before the repository is touched.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Solution {
+    pub blob: String,
+    pub hash: String,
+}
+
+/// A sorted set of disjoint, half open ranges of candidates.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Ranges(Vec<(u64, u64)>);
+
+impl Ranges {
+    /// Adds `start..end`, merging it with any ranges it touches.
+    pub fn insert(&mut self, start: u64, end: u64) {
+        let first = self.0.partition_point(|&(_, range_end)| range_end < start);
+        let last = self.0.partition_point(|&(range_start, _)| range_start <= end);
+
+        let (mut start, mut end) = (start, end);
+        if first < last {
+            start = start.min(self.0[first].0);
+            end = end.max(self.0[last - 1].1);
+        }
+        self.0.splice(first..last, Some((start, end)));
+    }
+
+    /// Whether all of `start..end` has been added.
+    pub fn contains(&self, start: u64, end: u64) -> bool {
+        let index = self.0.partition_point(|&(range_start, _)| range_start <= start);
+        index > 0 && self.0[index - 1].1 >= end
+    }
+
+    /// Number of candidates covered.
+    pub fn len(&self) -> u64 {
+        self.0.iter().map(|&(start, end)| end - start).sum()
+    }
+
+    /// Whether nothing has been added.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl Checkpoint {
+    /// Starts a checkpoint for mining `blob` with nothing searched yet.
+    pub fn new(blob: String, target: String, nonce_style: String, jitter: Option<String>) -> Checkpoint {
+        Checkpoint {
+            blob,
+            target,
+            nonce_style,
+            jitter,
+            timestamps: Ranges::default(),
+            nonces: Ranges::default(),
+            solution: None,
+        }
+    }
+
+    /// The file name the checkpoint is stored under, a digest of the commit before its nonce and
+    /// of how it is being mined.
+    pub fn key(&self) -> String {
+        let job = format!(
+            "{}\0{}\0{}\0{}",
+            self.blob,
+            self.target,
+            self.nonce_style,
+            self.jitter.as_deref().unwrap_or(""),
+        );
+        hasher::hash_blob(&job)
+    }
+
+    /// Whether this checkpoint is for the same search as `other`, apart from the dates in the
+    /// commit, which are different every time gitchain starts.
+    pub fn resumes(&self, other: &Checkpoint) -> bool {
+        writer::without_dates(&self.blob) == writer::without_dates(&other.blob)
+            && self.target == other.target
+            && self.nonce_style == other.nonce_style
+            && self.jitter == other.jitter
+    }
+
+    fn ranges(&mut self, space: Space) -> &mut Ranges {
+        match space {
+            Space::Timestamps => &mut self.timestamps,
+            Space::Nonces => &mut self.nonces,
+        }
+    }
+}
+
+/// A checkpoint being kept on disk while it is mined.  Workers record each chunk of candidates
+/// they finish, and the journal writes them out every `SAVE_INTERVAL`.
+pub struct Journal {
+    path: PathBuf,
+    checkpoint: Mutex<Checkpoint>,
+    last_saved: Mutex<Instant>,
+}
+
+impl Journal {
+    /// Keeps `checkpoint` in the checkpoint directory of the repository at `git_dir`.
+    pub fn new(git_dir: &Path, checkpoint: Checkpoint) -> Journal {
+        let path = git_dir.join(CHECKPOINT_DIR).join(format!("{}.json", checkpoint.key()));
+
+        Journal {
+            path,
+            checkpoint: Mutex::new(checkpoint),
+            last_saved: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Looks in the repository at `git_dir` for a checkpoint that `checkpoint` resumes, and keeps
+    /// the one with the most progress.  Starts afresh with `checkpoint` if there is none.
+    /// Checkpoints that can't be read, such as one cut short by a full disk, are passed over.
+    pub fn resume(git_dir: &Path, checkpoint: Checkpoint) -> Result<Journal, Error> {
+        let dir = git_dir.join(CHECKPOINT_DIR);
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(_) => return Ok(Journal::new(git_dir, checkpoint)),
+        };
+
+        let mut best = None;
+        for entry in entries.filter_map(Result::ok) {
+            let path = entry.path();
+            if path.extension().is_none_or(|extension| extension != "json") {
+                continue;
+            }
+
+            let saved = match Journal::load(&path) {
+                Ok(saved) => saved,
+                Err(_) => continue,
+            };
+            let progress = saved.timestamps.len().saturating_add(saved.nonces.len());
+            let done = saved.solution.is_some();
+            if saved.resumes(&checkpoint) && best.as_ref().is_none_or(|(best_done, best_progress, _)| {
+                (done, progress) > (*best_done, *best_progress)
+            }) {
+                best = Some((done, progress, saved));
+            }
+        }
+
+        let checkpoint = best.map_or(checkpoint, |(_, _, saved)| saved);
+        Ok(Journal::new(git_dir, checkpoint))
+    }
+
+    fn load(path: &Path) -> Result<Checkpoint, Error> {
+        let invalid = || CheckpointError::ReadError { path: path.display().to_string() };
+        let contents = fs::read_to_string(path).map_err(|_| invalid())?;
+        let checkpoint = serde_json::from_str(&contents).map_err(|_| invalid())?;
+
+        Ok(checkpoint)
+    }
+
+    /// A copy of the checkpoint as it stands.
+    pub fn checkpoint(&self) -> Checkpoint {
+        self.lock().clone()
+    }
+
+    /// Whether every candidate in `start..end` has already been tried.
+    pub fn is_done(&self, space: Space, start: u64, end: u64) -> bool {
+        self.lock().ranges(space).contains(start, end)
+    }
+
+    /// Records that every candidate in `start..end` has been tried, saving the checkpoint if it
+    /// hasn't been for a while.
+    pub fn complete(&self, space: Space, start: u64, end: u64) {
+        let mut checkpoint = self.lock();
+        checkpoint.ranges(space).insert(start, end);
+
+        if let Ok(mut last_saved) = self.last_saved.try_lock() {
+            if last_saved.elapsed() >= SAVE_INTERVAL && write(&self.path, &checkpoint).is_ok() {
+                *last_saved = Instant::now();
+            }
+        }
+    }
+
+    /// Records the solution and saves it straight away, so it survives whatever happens next.
+    pub fn solve(&self, blob: &str, hash: &str) -> Result<(), Error> {
+        let mut checkpoint = self.lock();
+        checkpoint.solution = Some(Solution { blob: blob.to_string(), hash: hash.to_string() });
+        write(&self.path, &checkpoint)
+    }
+
+    /// Saves the checkpoint as it stands.
+    pub fn save(&self) -> Result<(), Error> {
+        write(&self.path, &self.lock())
+    }
+
+    /// Deletes the checkpoint once its commit has been made.
+    pub fn remove(&self) -> Result<(), Error> {
+        if self.path.exists() {
+            fs::remove_file(&self.path)
+                .map_err(|_| CheckpointError::WriteError { path: self.path.display().to_string() })?;
+        }
+        Ok(())
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Checkpoint> {
+        self.checkpoint.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Writes a checkpoint to a temporary file and renames it into place, so a crash part way through
+/// never leaves a truncated checkpoint behind.
+fn write(path: &Path, checkpoint: &Checkpoint) -> Result<(), Error> {
+    let failed = || CheckpointError::WriteError { path: path.display().to_string() };
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|_| failed())?;
+    }
+
+    let contents = serde_json::to_string(checkpoint).map_err(|_| failed())?;
+    let temporary = path.with_extension("json.tmp");
+    fs::write(&temporary, contents).map_err(|_| failed())?;
+    fs::rename(&temporary, path).map_err(|_| failed())?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    const BLOB: &str = "tree TreeTest\n\
+                        author AuthorTest <test@test.com> 1454691142 -0000\n\
+                        committer AuthorTest <test@test.com> 1454691142 -0000\n\n\
+                        MessageTest";
+
+    fn checkpoint(blob: &str) -> Checkpoint {
+        Checkpoint::new(blob.to_string(), "prefix 000000".to_string(), "hex".to_string(), None)
+    }
+
+    #[test]
+    fn test_ranges_merge() {
+        let mut ranges = Ranges::default();
+        ranges.insert(10, 20);
+        ranges.insert(30, 40);
+        assert!(ranges.contains(12, 20));
+        assert!(!ranges.contains(15, 35));
+
+        ranges.insert(20, 30);
+        assert!(ranges.contains(10, 40));
+        assert_eq!(ranges.len(), 30);
+
+        ranges.insert(0, 5);
+        ranges.insert(50, 60);
+        ranges.insert(4, 52);
+        assert!(ranges.contains(0, 60));
+        assert_eq!(ranges.0, vec![(0, 60)]);
+    }
+
+    #[test]
+    fn test_journal_resumes_the_same_commit_with_other_dates() -> Result<(), Error> {
+        let dir = TempDir::new()?;
+        let journal = Journal::new(dir.path(), checkpoint(BLOB));
+        journal.complete(Space::Nonces, 0, 1 << 16);
+        journal.save()?;
+
+        let later = BLOB.replace("1454691142", "1454699999");
+        let resumed = Journal::resume(dir.path(), checkpoint(&later))?;
+        assert_eq!(resumed.checkpoint().blob, BLOB);
+        assert!(resumed.is_done(Space::Nonces, 0, 1 << 16));
+        assert!(!resumed.is_done(Space::Timestamps, 0, 1));
+
+        let other = Journal::resume(dir.path(), checkpoint(&BLOB.replace("MessageTest", "Other")))?;
+        assert!(!other.is_done(Space::Nonces, 0, 1));
+        Ok(())
+    }
+
+    #[test]
+    fn test_journal_passes_over_corrupt_checkpoints() -> Result<(), Error> {
+        let dir = TempDir::new()?;
+        let journal = Journal::new(dir.path(), checkpoint(BLOB));
+        journal.complete(Space::Nonces, 0, 1 << 16);
+        journal.save()?;
+        fs::write(dir.path().join(CHECKPOINT_DIR).join("corrupt.json"), "{\"blob\": ")?;
+
+        let resumed = Journal::resume(dir.path(), checkpoint(BLOB))?;
+        assert!(resumed.is_done(Space::Nonces, 0, 1 << 16));
+        Ok(())
+    }
+
+    #[test]
+    fn test_solution_is_saved_straight_away() -> Result<(), Error> {
+        let dir = TempDir::new()?;
+        let journal = Journal::new(dir.path(), checkpoint(BLOB));
+        journal.solve("mined", "000000abc")?;
+
+        let resumed = Journal::resume(dir.path(), checkpoint(BLOB))?;
+        assert_eq!(resumed.checkpoint().solution.map(|solution| solution.hash), Some("000000abc".to_string()));
+
+        resumed.remove()?;
+        assert!(fs::read_dir(dir.path().join(CHECKPOINT_DIR))?.next().is_none());
+        Ok(())
+    }
+}
@@ -0,0 +1,89 @@
+use crate::dates;
+use failure::Error;
+use time::Tm;
+
+/// Where a Committer gets the author and committer dates of a new commit from.  Implement this
+/// to make commits at dates of your own, such as when replaying history.
+pub trait Clock: Send + Sync {
+    /// The date the commit was authored.
+    fn author_date(&self) -> Result<Tm, Error>;
+
+    /// The date the commit was made.
+    fn committer_date(&self) -> Result<Tm, Error>;
+
+    /// Both dates, author first.  Override this if asking for them one at a time could give
+    /// dates that don't belong together, such as two readings of a running clock.
+    fn dates(&self) -> Result<(Tm, Tm), Error> {
+        Ok((self.author_date()?, self.committer_date()?))
+    }
+}
+
+/// The clock git uses: the dates in `GIT_AUTHOR_DATE` and `GIT_COMMITTER_DATE`, or else in
+/// `SOURCE_DATE_EPOCH`, or else the current time.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn author_date(&self) -> Result<Tm, Error> {
+        dates::author_date()
+    }
+
+    fn committer_date(&self) -> Result<Tm, Error> {
+        dates::committer_date()
+    }
+
+    fn dates(&self) -> Result<(Tm, Tm), Error> {
+        dates::commit_dates()
+    }
+}
+
+/// A clock that always gives the same dates, for tests and tools that need to know exactly which
+/// commit will be made.
+#[derive(Clone, Copy, Debug)]
+pub struct FixedClock {
+    author: Tm,
+    committer: Tm,
+}
+
+impl FixedClock {
+    /// Authors and commits every commit at `date`.
+    pub fn new(date: Tm) -> FixedClock {
+        FixedClock { author: date, committer: date }
+    }
+
+    /// Authors and commits every commit `seconds` after the epoch, in a timezone `offset` seconds
+    /// east of UTC.
+    pub fn at(seconds: i64, offset: i32) -> FixedClock {
+        FixedClock::new(dates::at_offset(seconds, offset))
+    }
+
+    /// Commits at `date` instead of the date the commit was authored.
+    pub fn with_committer_date(mut self, date: Tm) -> FixedClock {
+        self.committer = date;
+        self
+    }
+}
+
+impl Clock for FixedClock {
+    fn author_date(&self) -> Result<Tm, Error> {
+        Ok(self.author)
+    }
+
+    fn committer_date(&self) -> Result<Tm, Error> {
+        Ok(self.committer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fixed_clock() -> Result<(), Error> {
+        let clock = FixedClock::at(1454691142, 3600).with_committer_date(dates::at_offset(1454691660, 0));
+        assert_eq!(dates::epoch_seconds(&clock.author_date()?), 1454691142);
+        assert_eq!(clock.author_date()?.tm_utcoff, 3600);
+        assert_eq!(dates::epoch_seconds(&clock.committer_date()?), 1454691660);
+        Ok(())
+    }
+}
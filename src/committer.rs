@@ -1,93 +1,303 @@
-use crate::options::Options;
-use git2::Repository;
+use crate::backend;
+use crate::calibration::Calibration;
+use crate::cancel::CancellationToken;
+use crate::checkpoint::{Checkpoint, Journal};
+use crate::clock::{Clock, SystemClock};
+use crate::hooks::Hooks;
+use crate::identity::{Identity, RepositoryIdentity};
+use crate::jitter::Jitter;
+use crate::lock::RepoLock;
+use crate::message;
+use crate::nonce;
+use crate::target;
+use crate::estimate::Eta;
+use crate::options::{self, Options};
+use git2::{ErrorCode, ObjectType, Oid, Repository};
 pub use crate::miner::Miner;
 use crate::writer;
-use std::io::Write;
-use std::process::{Command, Stdio};
-use crate::errors::{RepositoryError, IoError, GitTerminalError};
+use crate::errors::RepositoryError;
 
 use failure::Error;
-use std::env;
-use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Git config key holding how long a search may be expected to take before gitchain warns about it.
+pub const WARN_AFTER_KEY: &str = "gitchain.warnAfter";
+
+/// How the reflog entries for gitchain's commits start, followed by the commit's subject.
+pub const REFLOG_PREFIX: &str = "commit (gitchain): ";
 
 /// A Committer does the work of issuing a git commit whose hash will match
 /// the desired prefix.
 pub struct Committer {
     miner: Miner,
-    working_dir: PathBuf,
+    repo: Repository,
+    cancel: CancellationToken,
+    timeout: Option<Duration>,
+    journal: Option<Arc<Journal>>,
+    warn_after: Option<Duration>,
+    within: Option<Duration>,
+    msg: String,
+    clock: Arc<dyn Clock>,
+    identity: Arc<dyn Identity>,
+    job: Option<Job>,
+    base: Base,
+    retry: bool,
+    retries: usize,
+    hooks: Hooks,
+    _lock: RepoLock,
+}
+
+/// What the repository looked like when the commit being mined was built from it.
+#[derive(Clone, Debug, PartialEq)]
+struct Base {
+    /// The branch HEAD is on, or `None` if HEAD is detached.
+    branch: Option<String>,
+    /// The commit HEAD points at, or `None` before the first commit.
+    parent: Option<Oid>,
+    /// The tree written from the index.
+    tree: Oid,
+}
+
+/// How a search is recorded in its checkpoint, apart from the commit being mined.
+struct Job {
+    target: String,
+    nonce_style: String,
+    jitter: Option<String>,
 }
 
 impl Committer {
     /// Creates a new Committer, and also in the process a new Miner to be used by the
-    /// Committer.
+    /// Committer.  Fails straight away for a target that can never be matched.  Holds the
+    /// repository's lock until the Committer is dropped.  Runs the hooks that can reject or
+    /// rewrite the commit first, so a commit they would reject is never mined.
     pub fn new(options: Options) -> Result<Committer, Error> {
-        let mut repo = Committer::get_repository(&options)?;
-        let working_dir = repo.workdir()
-            .ok_or(RepositoryError::WorkdirRetrievalError {})?.to_path_buf();
-
-        let tree = Committer::create_tree(&mut repo)?;
-        let parent = Committer::get_parent(&repo);
-        let author = Committer::get_author(&repo)?;
-
-        let blob = writer::generate_blob(
-            tree,
-            parent,
-            author,
-            options.msg,
-            time::now(),
-        )?;
-
-        let miner = Miner::new(
-            options.prefix,
+        target::ensure_satisfiable(options.predicate.as_ref())?;
+        let repo = Committer::get_repository(&options)?;
+        // Like git commit, there is nothing to commit from in a bare repository.
+        repo.workdir().ok_or(RepositoryError::WorkdirRetrievalError {})?;
+        let lock = RepoLock::acquire(repo.path())?;
+
+        let warn_after = match options.warn_after {
+            Some(limit) => Some(limit),
+            None => Committer::get_warn_after(&repo),
+        };
+
+        let hooks = Hooks::new(&repo)?;
+        let hooks = if options.no_verify { hooks.without_verification() } else { hooks };
+        hooks.pre_commit()?;
+        let msg = message::prepare(&repo, options.msg, &hooks)?;
+
+        let clock = options.clock.unwrap_or_else(|| Arc::new(SystemClock));
+        let identity = options.identity.unwrap_or_else(|| Arc::new(RepositoryIdentity));
+        let base = Committer::get_base(&repo)?;
+        let blob = Committer::build_blob(&repo, &base, identity.as_ref(), clock.as_ref(), &msg)?;
+
+        let backend = backend::select(options.backend.as_deref())?;
+        let encoding = nonce::select(options.nonce_style.as_deref())?;
+
+        // A search against the clock keeps its best hash in memory, so there is nothing to resume.
+        let within = options.within;
+        let jitter = options.jitter.as_ref().map(Jitter::describe);
+        let job = options.predicate.describe().filter(|_| within.is_none()).map(|target| Job {
+            target,
+            nonce_style: encoding.name().to_string(),
+            jitter,
+        });
+        let journal = match &job {
+            Some(job) => Some(Committer::open_journal(&repo, job, blob.clone(), options.resume)?),
+            None => None,
+        };
+        // A resumed search carries on with the dates it started with.
+        let blob = journal.as_ref().map_or(blob, |journal| journal.checkpoint().blob);
+
+        let mut miner = Miner::new(
+            options.predicate,
             blob,
-        );
+        ).with_backend(backend).with_encoding(encoding);
+        if let Some(jitter) = options.jitter {
+            miner = miner.with_jitter(jitter);
+        }
+        if let Some(observer) = options.observer {
+            miner = miner.with_observer(observer);
+        }
+        if let Some(journal) = &journal {
+            miner = miner.with_journal(journal.clone());
+        }
+        miner = miner.with_thread_pool(options.workers.build_pool()?);
+        if let Some(budget) = options.cpu_budget {
+            miner = miner.with_cpu_budget(budget);
+        }
+        if options.background {
+            miner = miner.in_background();
+        }
+        if options.deterministic {
+            miner = miner.deterministic();
+        }
 
-        return Ok(Committer {
+        Ok(Committer {
             miner,
-            working_dir,
+            repo,
+            cancel: options.cancel,
+            timeout: options.timeout,
+            journal,
+            warn_after,
+            within,
+            msg,
+            clock,
+            identity,
+            job,
+            base,
+            retry: options.retry,
+            retries: 0,
+            hooks,
+            _lock: lock,
         })
     }
 
-    /// This method can be called to commit files that have been staged.
-    pub fn commit(&mut self) -> Result<(), Error> {
-        let (blob, hash) = self.miner.solve()?;
+    /// How long mining the commit is expected to take, going by `calibration`.
+    pub fn eta(&self, calibration: &Calibration) -> Option<Eta> {
+        self.miner.eta(calibration)
+    }
 
-        self.commit_blob(&blob)?;
-        self.reset_head_to_hash(&hash)?;
+    /// How long a search may be expected to take before the user is warned about it: the limit
+    /// given in the options, or else the `gitchain.warnAfter` git config.
+    pub fn warn_after(&self) -> Option<Duration> {
+        self.warn_after
+    }
 
-        Ok(())
+    /// How many times the commit had to be mined again because HEAD moved or the index changed
+    /// while it was being mined.
+    pub fn retries(&self) -> usize {
+        self.retries
     }
 
-    fn reset_head_to_hash(&self, hash: &str) -> Result<(), Error> {
-        env::set_current_dir(&self.working_dir).is_ok();
+    /// This method can be called to commit files that have been staged.  Returns the hash of the
+    /// new commit.  If mining is cancelled, nothing is committed, but the progress made is saved
+    /// so that it can be resumed.  Only the branch HEAD is on moves; the index and work tree are
+    /// left as they are.  The post-commit hook runs once the branch has moved.  The timeout, if
+    /// there is one, starts counting here, not while the message was being written.
+    pub fn commit(&mut self) -> Result<String, Error> {
+        if let Some(timeout) = self.timeout.take() {
+            self.cancel = self.cancel.clone().with_timeout(timeout);
+        }
+
+        loop {
+            let saved = self.journal.as_ref()
+                .and_then(|journal| journal.checkpoint().solution)
+                .filter(|solution| self.miner.hash_blob(&solution.blob) == solution.hash);
 
-        Command::new("git")
-            .args(&["reset", "--hard", hash])
-            .output()
-            .map_err(|_| GitTerminalError::ResetHeadError {})?;
+            let (blob, hash) = match saved {
+                Some(solution) => (solution.blob, solution.hash),
+                None => self.mine()?,
+            };
 
-        Ok(())
+            let oid = self.write_commit(&blob, &hash)?;
+            match self.update_head(oid) {
+                Err(RepositoryError::HeadMovedError {}) | Err(RepositoryError::IndexChangedError {}) if self.retry => {
+                    self.start_over()?;
+                    continue;
+                }
+                updated => updated?,
+            }
+
+            // The commit is made, so a checkpoint left behind only costs a little disk space.
+            if let Some(journal) = &self.journal {
+                if let Err(error) = journal.remove() {
+                    eprintln!("Warning: {}", error);
+                }
+            }
+
+            self.hooks.post_commit();
+
+            return Ok(hash);
+        }
     }
 
-    fn commit_blob(&self, blob: &String) -> Result<(), Error> {
-        env::set_current_dir(&self.working_dir).is_ok();
+    /// Mines the commit, saving the solution before anything is written to the repository.
+    fn mine(&mut self) -> Result<(String, String), Error> {
+        let solved = match self.within {
+            Some(budget) => self.miner.solve_within(budget, &self.cancel),
+            None => self.miner.solve_cancellable(&self.cancel),
+        };
+
+        if let Some(journal) = &self.journal {
+            match &solved {
+                Ok((blob, hash)) => journal.solve(blob, hash)?,
+                Err(_) => journal.save()?,
+            }
+        }
 
-        let mut commit_command = Command::new("git")
-            .args(&["hash-object", "-t", "commit", "-w", "--stdin"])
-            .stdin(Stdio::piped())
-            .stdout(Stdio::null())
-            .spawn()
-            .map_err(|_| GitTerminalError::CommitObjectError {})?;
+        solved
+    }
 
-        let stdin = commit_command.stdin.as_mut()
-            .ok_or(IoError::StdinOpenError {})?;
+    /// Rebuilds the commit on top of the repository as it is now, and has the miner search for
+    /// it from scratch.  The pre-commit hook runs again first, as what it checked has changed.
+    fn start_over(&mut self) -> Result<(), Error> {
+        if let Some(journal) = &self.journal {
+            journal.remove()?;
+        }
 
-        stdin.write_all(blob.as_bytes())
-            .map_err(|_| IoError::StdinWriteError {} )?;
+        self.hooks.pre_commit()?;
+        self.base = Committer::get_base(&self.repo)?;
+        let blob = Committer::build_blob(&self.repo, &self.base, self.identity.as_ref(), self.clock.as_ref(), &self.msg)?;
+        self.journal = match &self.job {
+            Some(job) => Some(Committer::open_journal(&self.repo, job, blob.clone(), false)?),
+            None => None,
+        };
+        self.miner.start_over(blob, self.journal.clone());
+        self.retries += 1;
 
         Ok(())
     }
 
+    /// Writes the mined commit into the object database, checking that it hashes to what the
+    /// miner found.
+    fn write_commit(&self, blob: &str, hash: &str) -> Result<Oid, Error> {
+        let odb = self.repo.odb()
+            .map_err(|_| RepositoryError::ObjectWriteError {})?;
+
+        let oid = odb.write(ObjectType::Commit, blob.as_bytes())
+            .map_err(|_| RepositoryError::ObjectWriteError {})?;
+
+        if oid.to_string() != hash {
+            return Err(RepositoryError::HashMismatchError { expected: hash.to_string(), written: oid.to_string() }.into());
+        }
+
+        Ok(oid)
+    }
+
+    /// Points the branch HEAD is on at the new commit, creating the branch for a first commit, or
+    /// HEAD itself if it is detached.  The ref only moves if it still points at the commit's
+    /// parent, and only if the index still holds the commit's tree.
+    fn update_head(&self, oid: Oid) -> Result<(), RepositoryError> {
+        let current = Committer::get_base(&self.repo)
+            .map_err(|_| RepositoryError::RefUpdateError {})?;
+        if current.branch != self.base.branch || current.parent != self.base.parent {
+            return Err(RepositoryError::HeadMovedError {});
+        }
+        if current.tree != self.base.tree {
+            return Err(RepositoryError::IndexChangedError {});
+        }
+
+        let subject = self.msg.lines().next().unwrap_or("");
+        let log_message = format!("{}{}", REFLOG_PREFIX, subject);
+        let name = self.base.branch.as_deref().unwrap_or("HEAD");
+        let updated = match self.base.parent {
+            Some(parent) => self.repo.reference_matching(name, oid, true, parent, &log_message),
+            None => self.repo.reference(name, oid, false, &log_message),
+        };
+
+        match updated {
+            Ok(_) => Ok(()),
+            // Someone else got there between the check above and the update.
+            Err(ref error) if error.code() == ErrorCode::Modified || error.code() == ErrorCode::Exists => {
+                Err(RepositoryError::HeadMovedError {})
+            }
+            Err(_) => Err(RepositoryError::RefUpdateError {}),
+        }
+    }
+
     fn get_repository(options: &Options) -> Result<Repository, Error> {
         let repository = Repository::open(&options.repo)
             .map_err(|_| RepositoryError::OpenError {})?;
@@ -95,35 +305,60 @@ impl Committer {
         Ok(repository)
     }
 
-    fn get_author(repo: &Repository) -> Result<String, Error>{
-        let signature = repo.signature()
-            .map_err(|_| RepositoryError::SignatureRetrievalError {})?;
+    /// Reads the `gitchain.warnAfter` git config.  The limit only ever leads to a warning, so a
+    /// value that can't be parsed is warned about and ignored rather than stopping the commit.
+    fn get_warn_after(repo: &Repository) -> Option<Duration> {
+        let limit = repo.config().ok()?.get_string(WARN_AFTER_KEY).ok()?;
 
-        let name = signature.name()
-            .ok_or(RepositoryError::NameRetrievalError {})?;
+        match options::parse_duration(&limit) {
+            Ok(limit) => Some(limit),
+            Err(error) => {
+                eprintln!("Warning: ignoring {}: {}", WARN_AFTER_KEY, error);
+                None
+            }
+        }
+    }
 
-        let email = signature.email()
-            .ok_or(RepositoryError::EmailRetrievalError {})?;
+    fn open_journal(repo: &Repository, job: &Job, blob: String, resume: bool) -> Result<Arc<Journal>, Error> {
+        let checkpoint = Checkpoint::new(blob, job.target.clone(), job.nonce_style.clone(), job.jitter.clone());
+        let journal = if resume {
+            Journal::resume(repo.path(), checkpoint)?
+        } else {
+            Journal::new(repo.path(), checkpoint)
+        };
 
-        Ok(format!("{} <{}>", name, email))
+        Ok(Arc::new(journal))
     }
 
+    fn build_blob(repo: &Repository, base: &Base, identity: &dyn Identity, clock: &dyn Clock, msg: &str) -> Result<String, Error> {
+        let (author_date, committer_date) = clock.dates()?;
+        writer::generate_blob(
+            base.tree.to_string(),
+            base.parent.map(|parent| parent.to_string()),
+            identity.author(repo)?,
+            identity.committer(repo)?,
+            msg.to_string(),
+            author_date,
+            committer_date,
+        )
+    }
 
-    fn create_tree(repository: &mut Repository) -> Result<String, Error> {
-        let mut index = repository.index()
-            .map_err(|_| RepositoryError::FindIndexError {})?;
+    /// Reads where HEAD is and writes a tree from the index as it is on disk, which another
+    /// process may have changed since the repository was opened.
+    fn get_base(repo: &Repository) -> Result<Base, Error> {
+        let head = repo.find_reference("HEAD")
+            .map_err(|_| RepositoryError::OpenError {})?;
+        let branch = head.symbolic_target().map(str::to_string);
+        // An unborn branch has nothing to resolve to.
+        let parent = repo.refname_to_id("HEAD").ok();
 
+        let mut index = repo.index()
+            .map_err(|_| RepositoryError::FindIndexError {})?;
+        index.read(false)
+            .map_err(|_| RepositoryError::FindIndexError {})?;
         let tree = index.write_tree()
             .map_err(|_| RepositoryError::TreeWriteError {})?;
 
-        return Ok(format!("{}", tree));
+        Ok(Base { branch, parent, tree })
     }
-
-    fn get_parent(repository: &Repository) -> Option<String> {
-        if let Ok(head) = repository.revparse_single("HEAD") {
-            return Some(format!("{}", head.id()));
-        } else {
-            return None;
-        }
-    }
-}
\ No newline at end of file
+}
@@ -0,0 +1,143 @@
+use crate::errors::DateError;
+use failure::Error;
+use std::env;
+use time::{Timespec, Tm};
+
+/// Environment variable git reads the author date of a new commit from.
+pub const AUTHOR_DATE_VAR: &str = "GIT_AUTHOR_DATE";
+/// Environment variable git reads the committer date of a new commit from.
+pub const COMMITTER_DATE_VAR: &str = "GIT_COMMITTER_DATE";
+/// Environment variable reproducible builds use to fix every date, as seconds since the epoch.
+pub const SOURCE_DATE_EPOCH_VAR: &str = "SOURCE_DATE_EPOCH";
+
+/// Formats tried, in order, for dates that aren't seconds since the epoch.
+const DATE_FORMATS: [&str; 4] = [
+    "%Y-%m-%dT%H:%M:%S%z",
+    "%Y-%m-%d %H:%M:%S %z",
+    "%a, %d %b %Y %H:%M:%S %z",
+    "%a %b %d %H:%M:%S %Y %z",
+];
+
+/// The author date for a new commit: `GIT_AUTHOR_DATE`, or else `SOURCE_DATE_EPOCH`, or else now.
+pub fn author_date() -> Result<Tm, Error> {
+    date_from_env(AUTHOR_DATE_VAR, time::now())
+}
+
+/// The committer date for a new commit: `GIT_COMMITTER_DATE`, or else `SOURCE_DATE_EPOCH`, or
+/// else now.
+pub fn committer_date() -> Result<Tm, Error> {
+    date_from_env(COMMITTER_DATE_VAR, time::now())
+}
+
+/// The author and committer dates for a new commit, as `author_date` and `committer_date` give
+/// them, but reading the clock only once, so both are the same moment when neither is set.
+pub fn commit_dates() -> Result<(Tm, Tm), Error> {
+    let now = time::now();
+    Ok((date_from_env(AUTHOR_DATE_VAR, now)?, date_from_env(COMMITTER_DATE_VAR, now)?))
+}
+
+fn date_from_env(variable: &str, now: Tm) -> Result<Tm, Error> {
+    if let Ok(value) = env::var(variable) {
+        return parse_git_date(&value)
+            .ok_or_else(|| DateError::InvalidDateError { variable: variable.to_string(), value }.into());
+    }
+
+    if let Ok(value) = env::var(SOURCE_DATE_EPOCH_VAR) {
+        let seconds = value.trim().parse::<i64>()
+            .map_err(|_| DateError::InvalidDateError { variable: SOURCE_DATE_EPOCH_VAR.to_string(), value })?;
+        return Ok(at_offset(seconds, 0));
+    }
+
+    Ok(now)
+}
+
+/// Parses a date the way git does for `GIT_AUTHOR_DATE`: git's own `<seconds> <offset>` form,
+/// optionally with a leading `@`, ISO 8601, or RFC 2822.  Dates without an offset are UTC.
+pub fn parse_git_date(value: &str) -> Option<Tm> {
+    let value = value.trim();
+    let raw = value.strip_prefix('@').unwrap_or(value);
+    let mut fields = raw.split_whitespace();
+    if let Some(Ok(seconds)) = fields.next().map(str::parse::<i64>) {
+        let offset = match fields.next() {
+            Some(zone) => parse_offset(zone)?,
+            None => 0,
+        };
+        return match fields.next() {
+            Some(_) => None,
+            None => Some(at_offset(seconds, offset)),
+        };
+    }
+
+    DATE_FORMATS.iter()
+        .find_map(|format| time::strptime(value, format).ok())
+        .map(|parsed| at_offset(epoch_seconds(&parsed), parsed.tm_utcoff))
+}
+
+/// Seconds since the epoch of a time in any timezone.  `Tm::to_timespec` reads the fields of a
+/// time with an offset as if they were in the system's own timezone, so this works the seconds out
+/// from the fields and the time's own offset instead.
+pub fn epoch_seconds(time: &Tm) -> i64 {
+    Tm { tm_utcoff: 0, ..*time }.to_timespec().sec - i64::from(time.tm_utcoff)
+}
+
+/// Parses a timezone offset such as `+0100` or `-0530` into seconds east of UTC.
+fn parse_offset(zone: &str) -> Option<i32> {
+    let (sign, digits) = match zone.split_at_checked(1)? {
+        ("+", digits) => (1, digits),
+        ("-", digits) => (-1, digits),
+        _ => return None,
+    };
+    if digits.len() != 4 || !digits.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+
+    let hours: i32 = digits[..2].parse().ok()?;
+    let minutes: i32 = digits[2..].parse().ok()?;
+    Some(sign * (hours * 3600 + minutes * 60))
+}
+
+/// The time `seconds` after the epoch, as seen in a timezone `offset` seconds east of UTC.
+pub fn at_offset(seconds: i64, offset: i32) -> Tm {
+    let mut time = time::at_utc(Timespec::new(seconds + i64::from(offset), 0));
+    time.tm_utcoff = offset;
+    time
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn git_format(time: Tm) -> String {
+        format!("{} {}", epoch_seconds(&time), time.strftime("%z").unwrap())
+    }
+
+    #[test]
+    fn test_parsing_git_dates() {
+        let expected = "1454691142 +0100";
+        for date in [
+            "1454691142 +0100",
+            "@1454691142 +0100",
+            "2016-02-05T17:52:22+01:00",
+            "2016-02-05 17:52:22 +0100",
+            "Fri, 05 Feb 2016 17:52:22 +0100",
+            "Fri Feb 05 17:52:22 2016 +0100",
+        ] {
+            assert_eq!(parse_git_date(date).map(git_format).as_deref(), Some(expected), "{}", date);
+        }
+
+        assert_eq!(parse_git_date("@1454691142").map(git_format).as_deref(), Some("1454691142 -0000"));
+        assert!(parse_git_date("yesterday").is_none());
+        assert!(parse_git_date("1454691142 0100").is_none());
+        assert!(parse_git_date("1454691142 +0100 extra").is_none());
+    }
+
+    #[test]
+    fn test_commit_dates_read_the_clock_once() -> Result<(), Error> {
+        let (author, committer) = commit_dates()?;
+        if [AUTHOR_DATE_VAR, COMMITTER_DATE_VAR, SOURCE_DATE_EPOCH_VAR].iter().all(|variable| env::var(variable).is_err()) {
+            // Down to the nanosecond, which two readings of the clock would hardly ever agree on.
+            assert_eq!(author, committer);
+        }
+        Ok(())
+    }
+}
@@ -1,3 +1,6 @@
+// The `Fail` derive from failure_derive emits its impls inside an anonymous const.
+#![allow(non_local_definitions)]
+
 /// Errors originating from git2::Repository struct methods, translated for friendly error handling within
 /// the gitchain library.
 #[derive(Debug, Fail)]
@@ -16,38 +19,207 @@ pub enum RepositoryError {
     FindIndexError {},
     #[fail(display = "Failed to write tree.")]
     TreeWriteError {},
+    #[fail(display = "Failed to write the commit into the repository.")]
+    ObjectWriteError {},
+    #[fail(display = "The commit was written as {} instead of the mined {}. Nothing was committed.", written, expected)]
+    HashMismatchError { expected: String, written: String },
+    #[fail(display = "Failed to move HEAD to the new commit.")]
+    RefUpdateError {},
+    #[fail(display = "HEAD moved while mining, so the commit no longer fits on top of it. Nothing was committed. Use --retry to mine again.")]
+    HeadMovedError {},
+    #[fail(display = "The index changed while mining, so the commit no longer records what is staged. Nothing was committed. Use --retry to mine again.")]
+    IndexChangedError {},
+}
+
+/// Errors taking the lock that stops two gitchains committing to the same repository at once.
+#[derive(Debug, Fail)]
+pub enum LockError {
+    #[fail(display = "Another gitchain is committing to this repository, holding {}.", path)]
+    HeldError { path: String },
+    #[fail(display = "Failed to create lock {}.", path)]
+    CreateError { path: String },
+}
+
+/// Errors undoing gitchain's last commit.
+#[derive(Debug, Fail)]
+pub enum UndoError {
+    #[fail(display = "There is no gitchain commit on this branch to undo.")]
+    NothingToUndoError {},
+    #[fail(display = "Commits have been made on top of gitchain's last commit {}. Nothing was undone.", commit)]
+    CommittedOnTopError { commit: String },
+    #[fail(display = "Moved the branch back, but failed to restore the index. Run git reset to restore it.")]
+    IndexRestoreError {},
+}
+
+/// Errors running the git hooks around a commit.
+#[derive(Debug, Fail)]
+pub enum HookError {
+    #[fail(display = "Hooks only run in a repository with a working directory.")]
+    NoWorkdirError {},
+    #[fail(display = "Failed to run the {} hook.", hook)]
+    RunError { hook: String },
+    #[fail(display = "The {} hook rejected the commit. Nothing was mined. Use --no-verify to skip the hooks.", hook)]
+    RejectedError { hook: String },
+}
+
+/// Errors working out the message of a new commit.
+#[derive(Debug, Fail)]
+pub enum MessageError {
+    #[fail(display = "Aborting commit due to empty commit message. Nothing was mined.")]
+    EmptyError {},
+    #[fail(display = "Aborting commit; you did not edit the message. Nothing was mined.")]
+    UneditedTemplateError {},
+    #[fail(display = "Failed to read commit template {}.", path)]
+    TemplateError { path: String },
+    #[fail(display = "The editor {} failed. Nothing was mined.", editor)]
+    EditorError { editor: String },
+    #[fail(display = "Failed to write the commit message to {}.", path)]
+    MessageFileError { path: String },
 }
 
 /// General IO errors.
 #[derive(Debug, Fail)]
 pub enum IoError {
-    #[fail(display = "Failed to open stdin.")]
-    StdinOpenError {},
-    #[fail(display = "Failed to write to stdin.")]
-    StdinWriteError {},
+    #[fail(display = "Failed to read {}.", path)]
+    ReadFileError { path: String },
+    #[fail(display = "Failed to read from stdin.")]
+    ReadStdinError {},
 }
 
 /// Errors within the mining process, and always originating from the Miner struct.
 #[derive(Debug, Fail)]
 pub enum MiningError {
-    #[fail(display = "Failed to find a nonce that when hashed with the commit satisfied the prefix constraint.")]
+    #[fail(display = "Failed to find a nonce that when hashed with the commit satisfied the target predicate.")]
     SolveError {},
+    #[fail(display = "Mining was cancelled. Nothing was committed.")]
+    CancelledError {},
+    #[fail(display = "Mining timed out. Nothing was committed.")]
+    TimedOutError {},
+}
+
+/// Errors describing a target hash that could never be mined.
+#[derive(Debug, Fail)]
+pub enum TargetError {
+    #[fail(display = "Invalid prefix {}. Prefixes are lowercase hex, at most 40 digits long.", prefix)]
+    InvalidPrefixError { prefix: String },
+    #[fail(display = "Cannot require {} leading zero bits, a hash only has 160.", bits)]
+    TooManyBitsError { bits: u32 },
+    #[fail(display = "Invalid target {}. Targets are exactly 40 lowercase hex digits.", threshold)]
+    InvalidThresholdError { threshold: String },
+    #[fail(display = "A target of zero can never be mined, as no hash is less than it.")]
+    ZeroThresholdError {},
+    #[fail(display = "No prefixes were given, so no hash could ever match.")]
+    EmptyPrefixSetError {},
+    #[fail(display = "This target can never be matched, or its chance of matching is not known, so mining it might never end.")]
+    UnsatisfiableError {},
+}
+
+/// Errors parsing a hash pattern.
+#[derive(Debug, Fail)]
+pub enum PatternError {
+    #[fail(display = "Invalid pattern {}. Hashes are lowercase, so use lowercase hex digits.", pattern)]
+    UppercaseError { pattern: String },
+    #[fail(display = "Invalid character {:?} in pattern {}. Use hex digits, ?, # or %.", character, pattern)]
+    InvalidCharacterError { character: char, pattern: String },
+    #[fail(display = "Invalid pattern {}. A hash is only 40 digits long.", pattern)]
+    TooLongError { pattern: String },
+    #[fail(display = "Unknown pattern term {}. Expected prefix, suffix, contains or all.", term)]
+    UnknownTermError { term: String },
+    #[fail(display = "Unknown character class {}. Expected digits or letters.", class)]
+    UnknownClassError { class: String },
+    #[fail(display = "Pattern {} can never match a hash, as its terms contradict each other.", pattern)]
+    ContradictionError { pattern: String },
 }
 
 /// These errors relate to running git terminal commands internally within the library, and handling errors passed back from git.
 #[derive(Debug, Fail)]
 pub enum GitTerminalError {
-    #[fail(display = "Failed to reset head to new commit.")]
-    ResetHeadError {},
-    #[fail(display = "Failed to generate commit object.")]
-    CommitObjectError {},
     #[fail(display = "Failed to add files to staging.")]
     AddError {},
 }
 
+/// Errors choosing how the nonce is encoded into a commit.
+#[derive(Debug, Fail)]
+pub enum NonceError {
+    #[fail(display = "Unknown nonce style {}. Expected one of hex, trailer, whitespace or header.", name)]
+    UnknownStyleError { name: String },
+}
+
+/// Errors setting up how far the miner may move a commit's timestamps.
+#[derive(Debug, Fail)]
+pub enum JitterError {
+    #[fail(display = "Jitter window of {}s is too large.", seconds)]
+    WindowTooLargeError { seconds: u64 },
+}
+
+/// Errors reading or writing the checkpoints that let mining be resumed.
+#[derive(Debug, Fail)]
+pub enum CheckpointError {
+    #[fail(display = "Failed to read checkpoint {}.", path)]
+    ReadError { path: String },
+    #[fail(display = "Failed to write checkpoint {}.", path)]
+    WriteError { path: String },
+}
+
+/// Errors reading or writing the hashrate calibration saved by `gitchain bench`.
+#[derive(Debug, Fail)]
+pub enum CalibrationError {
+    #[fail(display = "Could not find a config directory to keep the calibration in. Set XDG_CONFIG_HOME or HOME.")]
+    NoConfigDirError {},
+    #[fail(display = "Failed to read calibration {}. Run gitchain bench again to replace it.", path)]
+    ReadError { path: String },
+    #[fail(display = "Failed to write calibration {}.", path)]
+    WriteError { path: String },
+}
+
+/// Errors reading the dates a commit is made with.
+#[derive(Debug, Fail)]
+pub enum DateError {
+    #[fail(display = "Invalid date in {}: {}. Expected seconds since the epoch, ISO 8601 or RFC 2822.", variable, value)]
+    InvalidDateError { variable: String, value: String },
+}
+
+/// Errors estimating how long a search will take.
+#[derive(Debug, Fail)]
+pub enum EstimateError {
+    #[fail(display = "The chance of a hash matching is not known, so there is nothing to estimate.")]
+    UnknownProbabilityError {},
+}
+
+/// Errors setting up the threads a miner runs on.
+#[derive(Debug, Fail)]
+pub enum WorkerError {
+    #[fail(display = "Invalid thread count {}. Use a whole number greater than zero.", value)]
+    InvalidThreadsError { value: String },
+    #[fail(display = "Failed to start {} mining threads.", threads)]
+    PoolError { threads: usize },
+}
+
+/// Errors parsing command line options.
+#[derive(Debug, Fail)]
+pub enum OptionsError {
+    #[fail(display = "Invalid duration {}. Use a number of seconds, or a number followed by s, m or h.", duration)]
+    InvalidDurationError { duration: String },
+    #[fail(display = "Invalid CPU budget {}. Use a percentage above 0 and at most 100, such as 50%.", budget)]
+    InvalidCpuBudgetError { budget: String },
+    #[fail(display = "Invalid hashrate {}. Expected a number of hashes per second, such as 250k or 12.5M.", hashrate)]
+    InvalidHashrateError { hashrate: String },
+}
+
 /// Errors from the Writer module.
 #[derive(Debug, Fail)]
 pub enum WriterErrors {
     #[fail(display = "Failed to format timestamp.")]
     TimeFormatError {},
 }
+
+/// Errors choosing or validating a SHA-1 backend.
+#[derive(Debug, Fail)]
+pub enum BackendError {
+    #[fail(display = "Unknown hash backend {}.", name)]
+    UnknownBackendError { name: String },
+    #[fail(display = "The {} hash backend is not supported by this CPU.", name)]
+    UnavailableError { name: String },
+    #[fail(display = "The {} hash backend failed its self-test.", name)]
+    SelfTestError { name: String },
+}
@@ -0,0 +1,131 @@
+use crate::target::HashPredicate;
+use std::time::Duration;
+
+/// How many attempts a search needs.  Every attempt succeeds independently with the same
+/// probability, so the number of attempts until the first success follows a geometric
+/// distribution: the mean is `1 / p`, and the median is only about 69% of that, but there is a
+/// long tail of unlucky searches.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Estimate {
+    probability: f64,
+}
+
+/// The time a search is expected to take at some hashrate.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Eta {
+    /// The mean time to solve.
+    pub expected: Duration,
+    /// Half of all searches finish within this long.
+    pub median: Duration,
+    /// 90% of all searches finish within this long.
+    pub p90: Duration,
+    /// 99% of all searches finish within this long.
+    pub p99: Duration,
+}
+
+impl Estimate {
+    /// An estimate for a search whose every attempt succeeds with `probability`.  Returns `None`
+    /// unless the probability is more than 0 and at most 1.
+    pub fn new(probability: f64) -> Option<Estimate> {
+        if probability > 0.0 && probability <= 1.0 {
+            Some(Estimate { probability })
+        } else {
+            None
+        }
+    }
+
+    /// An estimate for mining `predicate`, if its probability is known.
+    pub fn for_predicate(predicate: &dyn HashPredicate) -> Option<Estimate> {
+        predicate.probability().and_then(Estimate::new)
+    }
+
+    /// The chance of a single attempt succeeding.
+    pub fn probability(&self) -> f64 {
+        self.probability
+    }
+
+    /// The mean number of attempts until one succeeds.
+    pub fn expected_attempts(&self) -> f64 {
+        1.0 / self.probability
+    }
+
+    /// The number of attempts within which a share `quantile` of all searches succeed, such as
+    /// 0.9 for 90% of them.
+    pub fn attempts_within(&self, quantile: f64) -> f64 {
+        if self.probability >= 1.0 {
+            return 1.0;
+        }
+        // ln_1p keeps the precision that ln(1 - p) would lose for tiny probabilities.
+        ((-quantile).ln_1p() / (-self.probability).ln_1p()).max(1.0)
+    }
+
+    /// The times the search is expected to take at `hashrate` attempts per second.  Returns
+    /// `None` if the hashrate isn't positive.  Times too long for a `Duration` saturate at
+    /// `Duration::MAX`.
+    pub fn eta(&self, hashrate: f64) -> Option<Eta> {
+        if hashrate <= 0.0 {
+            return None;
+        }
+
+        let time = |attempts: f64| Duration::try_from_secs_f64(attempts / hashrate).unwrap_or(Duration::MAX);
+        Some(Eta {
+            expected: time(self.expected_attempts()),
+            median: time(self.attempts_within(0.5)),
+            p90: time(self.attempts_within(0.9)),
+            p99: time(self.attempts_within(0.99)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::target::Target;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() <= b * 1e-6
+    }
+
+    #[test]
+    fn test_geometric_quantiles() {
+        let estimate = Estimate::new(1.0 / 16f64.powi(7)).unwrap();
+        let expected = 16f64.powi(7);
+
+        assert!(close(estimate.expected_attempts(), expected));
+        assert!(close(estimate.attempts_within(0.5), expected * 2f64.ln()));
+        assert!(close(estimate.attempts_within(0.9), expected * 10f64.ln()));
+        assert!(close(estimate.attempts_within(0.99), expected * 100f64.ln()));
+    }
+
+    #[test]
+    fn test_estimate_for_predicates() {
+        let prefix = Estimate::for_predicate(&Target::prefix("0000").unwrap()).unwrap();
+        assert!(close(prefix.expected_attempts(), 65536.0));
+
+        let bits = Estimate::for_predicate(&Target::leading_zero_bits(20).unwrap()).unwrap();
+        assert!(close(bits.expected_attempts(), (1 << 20) as f64));
+
+        assert!(Estimate::new(0.0).is_none());
+        assert_eq!(Estimate::new(1.0).unwrap().attempts_within(0.99), 1.0);
+    }
+
+    #[test]
+    fn test_eta_at_a_hashrate() {
+        let estimate = Estimate::new(1.0 / 1_000_000.0).unwrap();
+        let eta = estimate.eta(1_000_000.0).unwrap();
+
+        assert_eq!(eta.expected, Duration::from_secs(1));
+        assert!(eta.median < eta.expected);
+        assert!(eta.expected < eta.p90 && eta.p90 < eta.p99);
+        assert!(estimate.eta(0.0).is_none());
+    }
+
+    #[test]
+    fn test_eta_saturates_for_hopeless_searches() {
+        let estimate = Estimate::for_predicate(&Target::leading_zero_bits(160).unwrap()).unwrap();
+        let eta = estimate.eta(1.0).unwrap();
+
+        assert_eq!(eta.expected, Duration::MAX);
+        assert_eq!(eta.p99, Duration::MAX);
+    }
+}
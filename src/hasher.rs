@@ -1,23 +1,92 @@
-use crypto::digest::Digest;
-use crypto::sha1::Sha1;
+use crypto::sha1::sha1_digest_block;
+use crate::backend::{HashBackend, Scalar, BLOCK_LEN, SHA1_INIT};
 use crate::writer;
 
+/// Length in bytes of a raw SHA-1 digest.
+pub const DIGEST_LEN: usize = 20;
+
 /// Hashes a blob by first using the writer struct to attach the necessary header to
-/// the blob, and then hash it using sha1, and returns the hash.
+/// the blob, and then hash it using sha1, and returns the hash.  Uses the portable scalar
+/// backend; see `hash_blob_with` to hash with another one.
 pub fn hash_blob(blob: &str) -> String {
-    let full_blob = writer::prepend_header_to_blob(&blob);
-    sha1_hash(&full_blob)
+    hash_blob_with(&Scalar, blob)
+}
+
+/// Hashes a blob like `hash_blob`, using `backend`'s `hash_object`.
+pub fn hash_blob_with(backend: &dyn HashBackend, blob: &str) -> String {
+    let full_blob = writer::prepend_header_to_blob(blob);
+    backend.hash_object(full_blob.as_bytes()).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Decodes 40 hex digits into a raw digest, for tests that check predicates against known hashes.
+#[cfg(test)]
+pub fn digest_from_hex(hex: &str) -> [u8; DIGEST_LEN] {
+    let mut digest = [0u8; DIGEST_LEN];
+    for (i, byte) in digest.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).unwrap();
+    }
+    digest
+}
+
+/// A SHA-1 state that has already compressed every full 64 byte block of the constant front of a
+/// commit object.  The rest of the front, room for the tail, the constant suffix after it and the
+/// SHA-1 padding are laid out in the final block(s), so finishing a hash only compresses those.
+pub struct Midstate {
+    state: [u32; 5],
+    tail: Vec<u8>,
+    tail_offset: usize,
 }
 
-fn sha1_hash(input: &str) -> String {
-    let mut sha1_hasher = Sha1::new();
-    sha1_hasher.input_str(&input);
-    sha1_hasher.result_str()
+impl Midstate {
+    /// Absorbs `front`, including its `commit <len>\0` header, and prepares final block(s) with
+    /// room for `tail_len` more bytes followed by `suffix`.
+    pub fn new(front: &[u8], tail_len: usize, suffix: &[u8]) -> Midstate {
+        let mut state = SHA1_INIT;
+        let absorbed = front.len() / BLOCK_LEN * BLOCK_LEN;
+        for block in front[..absorbed].chunks(BLOCK_LEN) {
+            sha1_digest_block(&mut state, block);
+        }
+
+        let remainder = &front[absorbed..];
+        let tail_offset = remainder.len();
+        let suffix_offset = tail_offset + tail_len;
+        let padded_len = (suffix_offset + suffix.len() + 9).div_ceil(BLOCK_LEN) * BLOCK_LEN;
+
+        let mut tail = vec![0u8; padded_len];
+        tail[..tail_offset].copy_from_slice(remainder);
+        tail[suffix_offset..suffix_offset + suffix.len()].copy_from_slice(suffix);
+        tail[suffix_offset + suffix.len()] = 0x80;
+        let length_bits = ((front.len() + tail_len + suffix.len()) as u64) * 8;
+        tail[padded_len - 8..].copy_from_slice(&length_bits.to_be_bytes());
+
+        Midstate {
+            state,
+            tail,
+            tail_offset,
+        }
+    }
+
+    /// Returns a copy of the final block(s), for a worker to write its tail bytes into.
+    pub fn tail_buffer(&self) -> Vec<u8> {
+        self.tail.clone()
+    }
+
+    /// Offset within the tail buffer where the tail bytes should be written.
+    pub fn tail_offset(&self) -> usize {
+        self.tail_offset
+    }
+
+    /// Finishes one hash per lane of `backend`, compressing each tail buffer (a copy of
+    /// `tail_buffer` with its tail bytes written in) and storing the raw digests in `digests`.
+    pub fn finish(&self, backend: &dyn HashBackend, buffers: &[Vec<u8>], digests: &mut [[u8; DIGEST_LEN]]) {
+        backend.hash_candidates(&self.state, buffers, digests);
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::backend;
 
     #[test]
     fn test_hasher_correctly_hashes_blob() {
@@ -28,5 +97,36 @@ mod tests {
                                 MessageTest";
         let hash = hash_blob(blob);
         assert_eq!(hash, "9dd04fe53bacc70ecd3da2a7880c001c5bd2ff4a");
+        for backend in backend::all().iter().filter(|backend| backend.is_available()) {
+            assert_eq!(hash_blob_with(backend.as_ref(), blob), hash, "{} backend", backend.name());
+        }
+    }
+
+    #[test]
+    fn test_midstate_matches_full_hash() {
+        for backend in backend::all().iter().filter(|backend| backend.is_available()) {
+            for message_len in 0..150 {
+                let message = "m".repeat(message_len);
+                let trailing = "s".repeat(message_len % 70);
+                let blob = format!("tree TreeTest\n\n{}\n0000002a\n{}", message, trailing);
+                let full = writer::prepend_header_to_blob(&blob);
+                let nonce_offset = full.find("0000002a").unwrap();
+                let (front, rest) = full.as_bytes().split_at(nonce_offset);
+                let (tail, suffix) = rest.split_at(8);
+
+                let midstate = Midstate::new(front, tail.len(), suffix);
+                let mut buffer = midstate.tail_buffer();
+                let offset = midstate.tail_offset();
+                buffer[offset..offset + tail.len()].copy_from_slice(tail);
+
+                let buffers = vec![buffer; backend.lanes()];
+                let mut digests = vec![[0u8; DIGEST_LEN]; backend.lanes()];
+                midstate.finish(backend.as_ref(), &buffers, &mut digests);
+                for digest in digests {
+                    let hex: String = digest.iter().map(|byte| format!("{:02x}", byte)).collect();
+                    assert_eq!(hex, hash_blob(&blob), "{} backend", backend.name());
+                }
+            }
+        }
     }
 }
@@ -0,0 +1,111 @@
+use crate::errors::HookError;
+use failure::Error;
+use git2::Repository;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// Git config key naming the directory hooks are kept in, instead of `.git/hooks`.
+pub const HOOKS_PATH_KEY: &str = "core.hooksPath";
+
+/// The hooks of a repository, run at the same points of making a commit as `git commit` runs them.
+#[derive(Clone, Debug)]
+pub struct Hooks {
+    dir: PathBuf,
+    workdir: PathBuf,
+    git_dir: PathBuf,
+    verify: bool,
+}
+
+impl Hooks {
+    /// Finds the hooks of `repo`: in the directory `core.hooksPath` names, taken relative to the
+    /// work tree, or else in `.git/hooks`.
+    pub fn new(repo: &Repository) -> Result<Hooks, Error> {
+        let workdir = repo.workdir().ok_or(HookError::NoWorkdirError {})?.to_path_buf();
+        let git_dir = repo.path().to_path_buf();
+
+        let configured = repo.config().ok().and_then(|config| config.get_path(HOOKS_PATH_KEY).ok());
+        let dir = match configured {
+            Some(dir) => workdir.join(dir),
+            None => git_dir.join("hooks"),
+        };
+
+        Ok(Hooks { dir, workdir, git_dir, verify: true })
+    }
+
+    /// Skips the pre-commit and commit-msg hooks, which verify the commit, like
+    /// `git commit --no-verify`.  The prepare-commit-msg and post-commit hooks still run.
+    pub fn without_verification(mut self) -> Hooks {
+        self.verify = false;
+        self
+    }
+
+    /// Runs the pre-commit hook, which can reject the commit before it is mined.
+    pub fn pre_commit(&self) -> Result<(), Error> {
+        if !self.verify {
+            return Ok(());
+        }
+        self.run("pre-commit", &[])
+    }
+
+    /// Runs the prepare-commit-msg hook on the message file at `path`, which it can rewrite before
+    /// the message is edited.  `source` says where the message came from, such as `message` or
+    /// `template`, as git passes it.
+    pub fn prepare_commit_msg(&self, path: &Path, source: Option<&str>) -> Result<(), Error> {
+        let file = path.display().to_string();
+        match source {
+            Some(source) => self.run("prepare-commit-msg", &[&file, source]),
+            None => self.run("prepare-commit-msg", &[&file]),
+        }
+    }
+
+    /// Runs the commit-msg hook on the finished message file at `path`, which it can rewrite or
+    /// reject.
+    pub fn commit_msg(&self, path: &Path) -> Result<(), Error> {
+        if !self.verify {
+            return Ok(());
+        }
+        self.run("commit-msg", &[&path.display().to_string()])
+    }
+
+    /// Runs the post-commit hook once the commit has been made.  Like git, gitchain takes no
+    /// notice of how it went, as the commit can no longer be stopped.
+    pub fn post_commit(&self) {
+        let _ = self.run("post-commit", &[]);
+    }
+
+    /// Runs the hook called `name` from the top of the work tree, if there is one and it is
+    /// executable, failing if it exits with anything but success.
+    fn run(&self, name: &str, args: &[&str]) -> Result<(), Error> {
+        let path = self.dir.join(name);
+        if !is_executable(&path) {
+            return Ok(());
+        }
+
+        let status = Command::new(&path)
+            .args(args)
+            .current_dir(&self.workdir)
+            .env("GIT_INDEX_FILE", self.git_dir.join("index"))
+            .stdin(Stdio::null())
+            .status()
+            .map_err(|_| HookError::RunError { hook: name.to_string() })?;
+
+        if !status.success() {
+            return Err(HookError::RejectedError { hook: name.to_string() }.into());
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    fs::metadata(path).map(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0).unwrap_or(false)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
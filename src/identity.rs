@@ -0,0 +1,65 @@
+use crate::errors::RepositoryError;
+use failure::Error;
+use git2::Repository;
+
+/// Where a Committer gets the names and emails of a new commit's author and committer from,
+/// written as `Name <email>`.  Implement this to make commits as someone of your own choosing.
+pub trait Identity: Send + Sync {
+    /// Who authored the commit.
+    fn author(&self, repo: &Repository) -> Result<String, Error>;
+
+    /// Who made the commit.  Defaults to the author.
+    fn committer(&self, repo: &Repository) -> Result<String, Error> {
+        self.author(repo)
+    }
+}
+
+/// The identity git uses by default: `user.name` and `user.email` from the repository's config.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RepositoryIdentity;
+
+impl Identity for RepositoryIdentity {
+    fn author(&self, repo: &Repository) -> Result<String, Error> {
+        let signature = repo.signature()
+            .map_err(|_| RepositoryError::SignatureRetrievalError {})?;
+
+        let name = signature.name()
+            .ok_or(RepositoryError::NameRetrievalError {})?;
+
+        let email = signature.email()
+            .ok_or(RepositoryError::EmailRetrievalError {})?;
+
+        Ok(format!("{} <{}>", name, email))
+    }
+}
+
+/// An identity that is the same whatever the repository says.
+#[derive(Clone, Debug)]
+pub struct FixedIdentity {
+    author: String,
+    committer: String,
+}
+
+impl FixedIdentity {
+    /// Authors and commits as `name <email>`.
+    pub fn new(name: &str, email: &str) -> FixedIdentity {
+        let identity = format!("{} <{}>", name, email);
+        FixedIdentity { author: identity.clone(), committer: identity }
+    }
+
+    /// Commits as `name <email>` instead of as the author.
+    pub fn with_committer(mut self, name: &str, email: &str) -> FixedIdentity {
+        self.committer = format!("{} <{}>", name, email);
+        self
+    }
+}
+
+impl Identity for FixedIdentity {
+    fn author(&self, _repo: &Repository) -> Result<String, Error> {
+        Ok(self.author.clone())
+    }
+
+    fn committer(&self, _repo: &Repository) -> Result<String, Error> {
+        Ok(self.committer.clone())
+    }
+}
@@ -0,0 +1,291 @@
+use crate::errors::JitterError;
+use failure::Error;
+use std::convert::TryFrom;
+use std::time::Duration;
+
+/// Smallest timezone offset, in minutes, the miner picks from when it varies timezones.
+const MIN_TIMEZONE: i64 = -12 * 60;
+/// Largest timezone offset, in minutes, the miner picks from when it varies timezones.
+const MAX_TIMEZONE: i64 = 14 * 60;
+/// Timezone offsets are varied in quarter hours, the finest step any real timezone uses.
+const TIMEZONE_STEP: i64 = 15;
+
+/// How far the miner may move the author and committer timestamps of a commit, so it can find a
+/// hash without writing a nonce into it.
+#[derive(Clone, Debug)]
+pub struct Jitter {
+    window: i64,
+    timezones: bool,
+}
+
+/// The author and committer timestamps of one particular commit, laid out so the miner can
+/// rewrite them in place.  Both signatures sit in a single region of the commit, from the
+/// author timestamp to the end of the committer line, which is rewritten for every attempt.
+pub struct Stamps {
+    jitter: Jitter,
+    offset: usize,
+    template: Vec<u8>,
+    author: Stamp,
+    committer: Stamp,
+}
+
+/// Where a timestamp and its timezone sit within the rewritten region, and their original values.
+struct Stamp {
+    offset: usize,
+    seconds: i64,
+    digits: usize,
+}
+
+impl Jitter {
+    /// Lets the miner move each timestamp up to `window` either side of the one in the commit.
+    /// Fails for a window too large to count in seconds.
+    pub fn new(window: Duration) -> Result<Jitter, Error> {
+        let seconds = window.as_secs();
+        let window = i64::try_from(seconds).map_err(|_| JitterError::WindowTooLargeError { seconds })?;
+
+        Ok(Jitter { window, timezones: false })
+    }
+
+    /// Also lets the miner pick any timezone offset for each signature.  The moment a commit was
+    /// made is unchanged, but `git log` shows it in that timezone.
+    pub fn with_timezones(mut self) -> Jitter {
+        self.timezones = true;
+        self
+    }
+
+    /// Describes the window and whether timezones are varied, such as `3600s with timezones`, to
+    /// tell one search from another.
+    pub fn describe(&self) -> String {
+        if self.timezones {
+            format!("{}s with timezones", self.window)
+        } else {
+            format!("{}s", self.window)
+        }
+    }
+
+    /// Number of different commits the miner can try, which bounds how hard a target it can hit
+    /// before it has to fall back to a nonce.
+    pub fn candidates(&self) -> u64 {
+        let per_signature = self.choices_per_signature();
+        per_signature.saturating_mul(per_signature)
+    }
+
+    /// Lays out the timestamps of `blob` for mining.  Returns `None` if the blob has no author or
+    /// committer line, or if moving a timestamp within the window could change its length.
+    pub fn stamps(&self, blob: &str) -> Option<Stamps> {
+        let headers = &blob[..blob.find("\n\n").unwrap_or(blob.len())];
+        let (author_start, author_end) = signature_time(headers, "author ")?;
+        let (committer_start, committer_end) = signature_time(headers, "committer ")?;
+        if committer_start < author_end {
+            return None;
+        }
+
+        let template = blob.as_bytes()[author_start..committer_end].to_vec();
+        let author = Stamp::parse(&blob[author_start..author_end], 0, self.window)?;
+        let committer = Stamp::parse(&blob[committer_start..committer_end], committer_start - author_start, self.window)?;
+
+        Some(Stamps {
+            jitter: self.clone(),
+            offset: author_start,
+            template,
+            author,
+            committer,
+        })
+    }
+
+    fn choices_per_signature(&self) -> u64 {
+        let times = 2 * self.window as u64 + 1;
+        times.saturating_mul(self.timezone_count())
+    }
+
+    fn timezone_count(&self) -> u64 {
+        if self.timezones {
+            ((MAX_TIMEZONE - MIN_TIMEZONE) / TIMEZONE_STEP + 1) as u64
+        } else {
+            1
+        }
+    }
+
+    /// Splits a choice for one signature into how far to move its timestamp, and its new timezone
+    /// in minutes if timezones are varied.  Small moves come first, alternating later and earlier.
+    fn choice(&self, choice: u64) -> (i64, Option<i64>) {
+        let times = 2 * self.window as u64 + 1;
+        let step = (choice % times) as i64;
+        let delta = if step % 2 == 1 { (step + 1) / 2 } else { -step / 2 };
+
+        let timezone = if self.timezones {
+            Some(MIN_TIMEZONE + (choice / times) as i64 * TIMEZONE_STEP)
+        } else {
+            None
+        };
+
+        (delta, timezone)
+    }
+}
+
+impl Stamps {
+    /// Offset within the blob of the region the miner rewrites.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Length of the region the miner rewrites.
+    pub fn len(&self) -> usize {
+        self.template.len()
+    }
+
+    /// Whether the region is empty, which it never is for a real commit.
+    pub fn is_empty(&self) -> bool {
+        self.template.is_empty()
+    }
+
+    /// Number of different commits the miner can try.
+    pub fn candidates(&self) -> u64 {
+        self.jitter.candidates()
+    }
+
+    /// Writes the region for candidate number `candidate` into the front of `buffer`, without
+    /// allocating.
+    pub fn write(&self, buffer: &mut [u8], candidate: u64) {
+        let per_signature = self.jitter.choices_per_signature();
+        let region = &mut buffer[..self.template.len()];
+        region.copy_from_slice(&self.template);
+
+        self.author.write(region, self.jitter.choice(candidate % per_signature));
+        self.committer.write(region, self.jitter.choice(candidate / per_signature));
+    }
+
+    /// Returns `blob` with the timestamps of candidate number `candidate` written into it.
+    pub fn apply(&self, blob: &str, candidate: u64) -> String {
+        let mut bytes = blob.as_bytes().to_vec();
+        self.write(&mut bytes[self.offset..], candidate);
+        String::from_utf8(bytes).unwrap_or_default()
+    }
+}
+
+impl Stamp {
+    /// Parses `<seconds> <timezone>` found at `offset` within the rewritten region.
+    fn parse(time: &str, offset: usize, window: i64) -> Option<Stamp> {
+        let (seconds, timezone) = time.split_at(time.find(' ')?);
+        if timezone.len() != 6 {
+            return None;
+        }
+
+        let digits = seconds.len();
+        let seconds = seconds.parse::<i64>().ok()?;
+        let earliest = seconds.checked_sub(window)?;
+        let latest = seconds.checked_add(window)?;
+        if earliest < 0 || earliest.to_string().len() != digits || latest.to_string().len() != digits {
+            return None;
+        }
+
+        Some(Stamp { offset, seconds, digits })
+    }
+
+    fn write(&self, region: &mut [u8], (delta, timezone): (i64, Option<i64>)) {
+        let seconds = &mut region[self.offset..self.offset + self.digits];
+        write_decimal(seconds, (self.seconds + delta) as u64);
+
+        if let Some(minutes) = timezone {
+            let start = self.offset + self.digits + 1;
+            let timezone = &mut region[start..start + 5];
+            timezone[0] = if minutes < 0 { b'-' } else { b'+' };
+            write_decimal(&mut timezone[1..3], (minutes.abs() / 60) as u64);
+            write_decimal(&mut timezone[3..5], (minutes.abs() % 60) as u64);
+        }
+    }
+}
+
+/// Finds the `<seconds> <timezone>` at the end of the signature line starting with `field`,
+/// returning its start and end offsets.
+fn signature_time(headers: &str, field: &str) -> Option<(usize, usize)> {
+    let mut line_start = 0;
+    for line in headers.split('\n') {
+        if line.starts_with(field) {
+            let time_start = line.rfind("> ")? + 2;
+            return Some((line_start + time_start, line_start + line.len()));
+        }
+        line_start += line.len() + 1;
+    }
+
+    None
+}
+
+fn write_decimal(buffer: &mut [u8], mut value: u64) {
+    for digit in buffer.iter_mut().rev() {
+        *digit = b'0' + (value % 10) as u8;
+        value /= 10;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLOB: &str = "tree TreeTest\n\
+                        author AuthorTest <test@test.com> 1454691142 -0000\n\
+                        committer AuthorTest <test@test.com> 1454691142 -0000\n\n\
+                        MessageTest";
+
+    #[test]
+    fn test_small_moves_come_first() -> Result<(), Error> {
+        let jitter = Jitter::new(Duration::from_secs(60))?;
+        let stamps = jitter.stamps(BLOB).unwrap();
+        assert_eq!(jitter.candidates(), 121 * 121);
+
+        assert_eq!(stamps.apply(BLOB, 0), BLOB);
+        assert!(stamps.apply(BLOB, 1).contains("author AuthorTest <test@test.com> 1454691143 -0000\n"));
+        assert!(stamps.apply(BLOB, 2).contains("author AuthorTest <test@test.com> 1454691141 -0000\n"));
+        assert!(stamps.apply(BLOB, 121 * 4).contains("committer AuthorTest <test@test.com> 1454691140 -0000\n"));
+        assert!(stamps.apply(BLOB, 121 * 4).ends_with("\n\nMessageTest"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_timezones_are_varied() -> Result<(), Error> {
+        let jitter = Jitter::new(Duration::from_secs(0))?.with_timezones();
+        let stamps = jitter.stamps(BLOB).unwrap();
+        assert_eq!(jitter.candidates(), 105 * 105);
+
+        assert!(stamps.apply(BLOB, 0).contains("author AuthorTest <test@test.com> 1454691142 -1200\n"));
+        assert!(stamps.apply(BLOB, 104).contains("author AuthorTest <test@test.com> 1454691142 +1400\n"));
+        assert!(stamps.apply(BLOB, 105 * 51).contains("committer AuthorTest <test@test.com> 1454691142 +0045\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_describe() -> Result<(), Error> {
+        assert_eq!(Jitter::new(Duration::from_secs(3600))?.describe(), "3600s");
+        assert_eq!(Jitter::new(Duration::from_secs(60))?.with_timezones().describe(), "60s with timezones");
+        Ok(())
+    }
+
+    #[test]
+    fn test_written_region_matches_applied_blob() -> Result<(), Error> {
+        let jitter = Jitter::new(Duration::from_secs(300))?.with_timezones();
+        let stamps = jitter.stamps(BLOB).unwrap();
+        for &candidate in &[0, 7, 601 * 105 + 3, jitter.candidates() - 1] {
+            let applied = stamps.apply(BLOB, candidate);
+            let mut region = vec![0u8; stamps.len()];
+            stamps.write(&mut region, candidate);
+            assert_eq!(&applied.as_bytes()[stamps.offset()..stamps.offset() + stamps.len()], &region[..]);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_timestamps_that_would_change_length_are_refused() -> Result<(), Error> {
+        let blob = BLOB.replace("1454691142", "1000000000");
+        assert!(Jitter::new(Duration::from_secs(1))?.stamps(&blob).is_none());
+        assert!(Jitter::new(Duration::from_secs(0))?.stamps(&blob).is_some());
+        assert!(Jitter::new(Duration::from_secs(1))?.stamps("tree TreeTest\n\nMessageTest").is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_windows_too_large_are_refused() -> Result<(), Error> {
+        assert!(Jitter::new(Duration::from_secs(u64::MAX)).is_err());
+        assert!(Jitter::new(Duration::from_secs(i64::MAX as u64))?.stamps(BLOB).is_none());
+        Ok(())
+    }
+}
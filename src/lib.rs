@@ -64,6 +64,45 @@
 //! $ gitchain commit -p 010101 -m "Commit message."
 //! ```
 //!
+//! If any of several prefixes will do, pass them all with the --prefixes flag, or put them in a file
+//! with one prefix per line and pass it with --prefix-file. Gitchain stops at the first hash that
+//! starts with any of them and tells you which one it matched. Checking thousands of prefixes costs
+//! about the same as checking one:
+//!
+//! ```console
+//! $ gitchain commit --prefixes cafe,beef,c0ffee -m "Commit message."
+//! $ gitchain commit --prefix-file hexspeak.txt -m "Commit message."
+//! ```
+//!
+//! Each extra hex digit of a prefix makes a commit sixteen times more expensive to mine. For finer
+//! control, you can instead ask for a number of leading zero bits, where each extra bit only doubles
+//! the cost:
+//!
+//! ```console
+//! $ gitchain commit --bits 26 -m "Commit message."
+//! ```
+//!
+//! Or give a 160 bit target as 40 hex digits, and the hash must be numerically less than it:
+//!
+//! ```console
+//! $ gitchain commit --target 0000003fffffffffffffffffffffffffffffffff -m "Commit message."
+//! ```
+//!
+//! For anything other than a prefix, use the --pattern flag. A pattern is a comma separated list of
+//! terms, all of which the hash has to match:
+//!
+//! * `prefix:TEMPLATE` (or just `TEMPLATE`) - the hash starts with the template.
+//! * `suffix:TEMPLATE` - the hash ends with the template.
+//! * `contains:TEMPLATE` - the template appears anywhere in the hash.
+//! * `all:digits` or `all:letters` - every digit of the hash is `0-9`, or every one is `a-f`.
+//!
+//! Templates are lowercase hex digits plus `?` for any digit, `#` for `0-9` and `%` for `a-f`. The
+//! same templates work with --prefix.
+//!
+//! ```console
+//! $ gitchain commit --pattern "prefix:00??00,suffix:####" -m "Commit message."
+//! ```
+//!
 //! By default `gitchain` will use the current directory as the root directory for the git repo.
 //! If you would like to supply an alternate path, simply pass it using the -r or --repository flag:
 //!
@@ -71,20 +110,181 @@
 //! $ gitchain commit -r ~/git/custom_folder -m "Commit message"
 //! ```
 //!
-//! ## Testing
+//! Gitchain detects the fastest SHA-1 implementation your CPU supports when it starts mining. It
+//! prefers the x86 SHA extensions, then an AVX2 kernel that hashes eight candidates at once, and
+//! falls back to a portable implementation everywhere else. Each one is checked against known
+//! answers before it is used. To force a particular one, pass it using the --backend flag:
 //!
-//! To run tests simply change into the root directory for the crate and run:
+//! ```console
+//! $ gitchain commit --backend avx2 -m "Commit message"
+//! ```
+//!
+//! By default the nonce is written in hex on its own line at the end of the commit message. If that
+//! line gets in the way, pick another place for it with the --nonce-style flag:
+//!
+//! * `hex` - the nonce on its own line at the end of the message (the default).
+//! * `trailer` - a `Gitchain-Nonce:` trailer, next to any trailers the message already has.
+//! * `whitespace` - trailing spaces and tabs after the message, which `git log` doesn't show.
+//! * `header` - a `gitchain-nonce` commit header, which only `git log --pretty=raw` shows.
 //!
 //! ```console
-//! $ cargo test
+//! $ gitchain commit --nonce-style trailer -m "Commit message"
+//! ```
+//!
+//! If your commit messages have to stay exactly as written, pass a window with the --jitter flag.
+//! Gitchain then moves the author and committer timestamps up to that far either way, such as `90s`,
+//! `10m` or `1h`, and leaves the message alone. Add --jitter-timezones to let it pick the timezone
+//! of each timestamp too, which gives it far more to work with. If no combination in the window
+//! works, gitchain falls back to adding a nonce, and it goes straight to the nonce when the window is
+//! far too small to hold a match:
+//!
+//! ```console
+//! $ gitchain commit --jitter 10m -p 0000 -m "Commit message"
+//! ```
+//!
+//! While it mines, gitchain keeps a progress line on the terminal with the number of hashes tried,
+//! the hashrate, the time spent, an ETA and the best hash found so far. To get the full stats
+//! printed to stderr, including the hashrate of each thread, send it SIGUSR1:
+//!
+//! ```console
+//! $ pkill -USR1 gitchain
+//! ```
+//!
+//! Press Ctrl-C to stop mining, or pass the --timeout flag to give up after a while. Either way the
+//! workers stop cleanly and nothing is committed. Gitchain exits with code 130 when it was cancelled
+//! and 124 when it timed out, so scripts can tell an aborted search from a real failure:
+//!
+//! ```console
+//! $ gitchain commit --timeout 5m -p 0000000 -m "Commit message"
+//! ```
+//!
+//! Long searches are checkpointed as they go. Gitchain saves which nonces it has tried under
+//! `.git/gitchain/` every few seconds and whenever it stops, and saves the mined commit there before
+//! it touches the repository. If a search is interrupted, run the same command again with --resume
+//! to pick up where it stopped, with the dates it started with. A solution that was found but never
+//! committed is committed straight away:
+//!
+//! ```console
+//! $ gitchain commit --resume -p 0000000000 -m "Commit message"
+//! ```
+//!
+//! Gitchain runs one mining thread for every CPU it may use. Inside a container it reads the
+//! cgroup CPU quota, so it doesn't start more threads than it will be given time for. To choose the
+//! number yourself, pass the -j or --threads flag, or set `GITCHAIN_THREADS`. Add --pin-threads to
+//! pin each thread to a core of its own:
+//!
+//! ```console
+//! $ gitchain commit --threads 4 --pin-threads -m "Commit message"
+//! $ GITCHAIN_THREADS=2 gitchain commit -m "Commit message"
+//! ```
+//!
+//! To keep using your machine while gitchain mines, pass --cpu-budget with the share of each thread's
+//! time it may use, and it will rest between bursts of work to stay near it. Pass --background to
+//! mine at the lowest scheduling priority instead, so gitchain only gets the CPU when nothing else
+//! wants it. On Linux this uses `SCHED_IDLE`; other systems can only lower the priority of the
+//! whole process, so there it has no effect. The two can be combined:
+//!
+//! ```console
+//! $ gitchain commit --cpu-budget 50% --background -m "Commit message"
+//! ```
+//!
+//! To find out how fast your machine mines, run the bench command. It mines for half a second with
+//! every SHA-1 backend your CPU supports, at every power of two threads up to the number gitchain
+//! would use, and saves the hashrates to `gitchain/calibration.json` in `$XDG_CONFIG_HOME` or
+//! `~/.config`. From then on, commit says how long it expects mining to take before it starts:
+//!
+//! ```console
+//! $ gitchain bench
+//! $ gitchain commit -p 00000000 -m "Commit message"
+//! Expected to take about 4m 52s, and 99% of the time within 22m 23s.
+//! ```
+//!
+//! To see what a target costs before mining it, run the estimate command with the same prefix,
+//! pattern or bits flags as commit. Every attempt is as likely to succeed as the last, so some
+//! searches are quick and some are much slower than expected: estimate gives the median time, and the
+//! time within which 90% and 99% of searches finish, using the hashrate bench measured or the one you
+//! pass with --hashrate. To have commit warn you before a search that will probably take longer than
+//! some limit, pass --warn-after or set it in your git config. The warning needs the hashrate bench
+//! measured, so run bench first:
+//!
+//! ```console
+//! $ gitchain estimate -p 0000000
+//! $ gitchain estimate --bits 40 --hashrate 50M
+//! $ git config --global gitchain.warnAfter 10m
+//! ```
+//!
+//! If you'd rather spend a fixed amount of time than aim for a fixed target, pass --within. Gitchain
+//! mines for that long and then commits the best hash it found: the one with the most leading zeros,
+//! or with a target, the one that comes closest to it. For a pattern, that is the one matching the
+//! most digits. It stops early if the target is met:
+//!
+//! ```console
+//! $ gitchain commit --within 30s -m "Commit message"
+//! $ gitchain commit --within 1m --pattern suffix:beef -m "Commit message"
+//! ```
+//!
+//! Gitchain normally commits whichever solution a thread finds first, so mining the same commit twice
+//! can give different hashes. Pass --deterministic to always commit the lowest nonce that works
+//! instead, whatever the number of threads or the backend. Gitchain also takes the commit dates from
+//! `GIT_AUTHOR_DATE` and `GIT_COMMITTER_DATE`, or from `SOURCE_DATE_EPOCH`, just as git does, so
+//! with the same staged files, message and dates, two machines mine byte for byte the same commit:
+//!
+//! ```console
+//! $ SOURCE_DATE_EPOCH=1454691142 gitchain commit --deterministic -m "Commit message"
 //! ```
 //!
-//! The integration test is currently set to ignore.  To run it simply pass the ignored flag:
+//! Gitchain only moves the branch HEAD is on, and leaves the index and work tree just as they are, so
+//! unstaged changes are safe. The reflog records the commit as `commit (gitchain): <subject>`. If
+//! HEAD moves or the index changes while a commit is being mined, the commit no longer fits, so
+//! gitchain refuses to make it. Pass --retry to have it mined again on top of the new HEAD and index
+//! instead. Only one gitchain commits to a repository at a time, holding `.git/gitchain.lock` while
+//! it does:
 //!
 //! ```console
-//! $ cargo test -- --ignored
+//! $ gitchain commit --retry -m "Commit message"
 //! ```
 //!
+//! To take back the last commit gitchain made, say one that landed on the wrong branch, run undo. The
+//! branch goes back to the commit's parent and what was committed is staged again, while the work
+//! tree is left alone. Gitchain finds its commit through the reflog, and won't undo it once other
+//! commits have been made on top:
+//!
+//! ```console
+//! $ gitchain undo
+//! ```
+//!
+//! Gitchain runs the repository's hooks like `git commit` does, from `.git/hooks` or the directory
+//! `core.hooksPath` names. The pre-commit, prepare-commit-msg and commit-msg hooks run before any
+//! mining starts, so a commit they reject or rewrite never wastes CPU, and post-commit runs once the
+//! branch has moved. With --retry, pre-commit runs again whenever the commit is mined again. Pass
+//! --no-verify to skip pre-commit and commit-msg, as git does:
+//!
+//! ```console
+//! $ gitchain commit --no-verify -m "Commit message"
+//! ```
+//!
+//! Without -m, gitchain opens the same editor git would, from `GIT_EDITOR` or `core.editor`, on a
+//! message started from `commit.template`. Give -m more than once for a message of several paragraphs,
+//! or take the message from a file with -F, or from stdin with `-F -`. An empty message aborts the
+//! commit before any mining starts:
+//!
+//! ```console
+//! $ gitchain commit
+//! $ gitchain commit -m "Subject" -m "A paragraph explaining why."
+//! $ git log -1 --format=%B | gitchain commit -F -
+//! ```
+//!
+//! ## Testing
+//!
+//! To run tests simply change into the root directory for the crate and run:
+//!
+//! ```console
+//! $ cargo test
+//! ```
+//!
+//! That runs the integration tests too, which mine commits into throwaway repositories in temporary
+//! directories, so they never touch the repository you run them from.
+//!
 //! ## Benchmarking
 //!
 //! To run criterion benchmarks simply cd into the root directory and run:
@@ -106,29 +306,83 @@
 
 #[macro_use]
 extern crate failure;
-#[macro_use]
 extern crate serde_derive;
 
 extern crate serde;
 
+/// backend contains the HashBackend trait, the SHA-1 implementations shipped with gitchain, and
+/// the registry the miner picks one from based on the features of the current CPU.
+pub mod backend;
+/// calibration contains the hashrates measured by `gitchain bench`, and where they are saved.
+pub mod calibration;
+/// cancel contains the CancellationToken used to stop a miner early, and the exit codes for doing so.
+pub mod cancel;
+/// checkpoint contains the Journal that saves mining progress under `.git/gitchain/` so it can be resumed.
+pub mod checkpoint;
+/// clock contains the Clock trait, through which a Committer gets the dates of a new commit.
+pub mod clock;
 /// committer contains the Committer struct which kicks off mining and issues a commit whose hash
 /// prefix will match the target.
 pub mod committer;
+/// dates contains the parser for the dates git accepts, and the environment variables that fix a
+/// commit's dates.
+pub mod dates;
 /// custom in-house errors that we translate to from other errors received by external crates.
 pub mod errors;
+/// estimate contains the Estimate struct, which works out how long a search is likely to take.
+pub mod estimate;
 /// hasher contains methods for hashing a blob.
 pub mod hasher;
+/// hooks contains the Hooks struct, which runs a repository's git hooks around a commit.
+pub mod hooks;
+/// identity contains the Identity trait, through which a Committer gets the author and committer of a new commit.
+pub mod identity;
+/// jitter contains the Jitter struct, which lets the miner vary commit timestamps instead of adding a nonce.
+pub mod jitter;
+/// lock contains the RepoLock struct, which stops two gitchains committing to the same repository at once.
+pub mod lock;
+/// message contains the Message enum, and works out a commit's message from the editor, hooks and templates like git does.
+pub mod message;
 /// miner contains the Miner struct which handles solving the Proof of Work in parallel.
 pub mod miner;
+/// nonce contains the NonceEncoding trait and the built-in ways of writing a nonce into a commit.
+pub mod nonce;
 /// options contains Structopt enum for parsing terminal commands and providing helpful menus.
 pub mod options;
+/// pattern contains the parser for the hash pattern language used by the --pattern flag.
+pub mod pattern;
+/// progress contains the MiningObserver trait, through which a miner reports how its search is going.
+pub mod progress;
+/// target contains the HashPredicate trait and the pre-decoded forms of the hash a miner is searching for.
+pub mod target;
+/// throttle contains the CpuBudget struct and the priority lowering used to mine in the background.
+pub mod throttle;
+/// undo contains the function that takes back gitchain's last commit, found through the reflog.
+pub mod undo;
+/// workers contains the Workers struct, which sizes and builds the thread pool a miner runs on.
+pub mod workers;
 /// writer contains methods for building and manipulating git blobs.
 pub mod writer;
 
+use crate::calibration::Calibration;
+use crate::cancel::CancellationToken;
 use crate::committer::Committer;
-use crate::errors::GitTerminalError;
+use crate::errors::{EstimateError, GitTerminalError, IoError};
+use crate::estimate::Estimate;
+use crate::hasher::DIGEST_LEN;
+use crate::jitter::Jitter;
+use crate::message::Message;
 pub use crate::options::{Options, Opts};
+use crate::pattern::Pattern;
+use crate::progress::{format_duration, format_hashrate, MiningObserver, ProgressLine};
+use crate::target::{HashPredicate, PrefixSet, Target, DEFAULT_PREFIX};
+use crate::workers::Workers;
+use std::fs;
+use std::io::{self, Read};
+use std::path::PathBuf;
 use std::process::Command;
+use std::sync::Arc;
+use std::time::Duration;
 
 use failure::Error;
 
@@ -136,21 +390,294 @@ use failure::Error;
 /// passed by the user, and run the appropriate internal functions.
 pub fn run(config: Opts) -> Result<(), Error> {
     match config {
-        Opts::Commit { repo, msg, prefix } => commit(Options { repo, msg, prefix }),
+        Opts::Commit {
+            repo,
+            msg,
+            file,
+            prefix,
+            prefixes,
+            prefix_file,
+            pattern,
+            bits,
+            threshold,
+            backend,
+            nonce_style,
+            jitter,
+            jitter_timezones,
+            timeout,
+            within,
+            deterministic,
+            resume,
+            retry,
+            no_verify,
+            threads,
+            pin_threads,
+            cpu_budget,
+            background,
+            warn_after,
+        } => {
+            let set = prefix_set(prefixes, prefix_file)?;
+            let untargeted = prefix.is_none() && pattern.is_none() && bits.is_none() && threshold.is_none();
+            let predicate: Box<dyn HashPredicate> = match &set {
+                Some(set) => Box::new(set.clone()),
+                // Given only a time limit, no hash is good enough and the most leading zeros wins.
+                None if within.is_some() && untargeted => Box::new(Target::leading_zero_bits((DIGEST_LEN * 8) as u32)?),
+                None => predicate(prefix, pattern, bits, threshold)?,
+            };
+
+            let jitter = match jitter {
+                Some(window) if jitter_timezones => Some(Jitter::new(window)?.with_timezones()),
+                Some(window) => Some(Jitter::new(window)?),
+                None => None,
+            };
+
+            let observer: Arc<dyn MiningObserver> = Arc::new(ProgressLine::new());
+            let hash = commit(Options {
+                repo,
+                msg: message(msg, file)?,
+                predicate,
+                backend,
+                nonce_style,
+                jitter,
+                observer: Some(observer),
+                clock: None,
+                identity: None,
+                cancel: CancellationToken::new(),
+                timeout,
+                resume,
+                retry,
+                no_verify,
+                within,
+                deterministic,
+                workers: workers(threads, pin_threads),
+                cpu_budget,
+                background,
+                warn_after,
+            })?;
+            if let Some(word) = set.as_ref().and_then(|set| set.matching_hash(&hash)) {
+                println!("Matched prefix {}.", word);
+            }
+            Ok(())
+        }
+        Opts::Estimate {
+            prefix,
+            prefixes,
+            prefix_file,
+            pattern,
+            bits,
+            threshold,
+            hashrate,
+            backend,
+            threads,
+        } => {
+            let predicate: Box<dyn HashPredicate> = match prefix_set(prefixes, prefix_file)? {
+                Some(set) => Box::new(set),
+                None => predicate(prefix, pattern, bits, threshold)?,
+            };
+            estimate(predicate.as_ref(), hashrate, backend, threads)
+        }
+        Opts::Bench { threads } => bench(threads),
+        Opts::Undo { repo } => {
+            let undone = undo::undo(&repo)?;
+            let branch = undone.branch.as_deref().map_or("HEAD", |branch| branch.trim_start_matches("refs/heads/"));
+            match undone.parent {
+                Some(parent) => println!("Undid {}. {} is back at {}, with its changes staged.", undone.commit, branch, parent),
+                None => println!("Undid {}. {} has no commits again, with its changes staged.", undone.commit, branch),
+            }
+            Ok(())
+        }
         Opts::Add { path } => add(path),
     }
 }
 
-fn commit(opts: Options) -> Result<(), Error> {
+fn commit(opts: Options) -> Result<String, Error> {
+    let budget = opts.within;
+    let cancel = opts.cancel.clone();
+    // The editor and the hooks have had the terminal, and Ctrl-C, until now.
     let mut committer = Committer::new(opts)?;
-    committer.commit()?;
-    println!("Successfully committed with desired prefix.");
+    #[cfg(unix)]
+    cancel.cancel_on_ctrl_c()?;
+    // A missing or unreadable calibration only costs the estimate, never the commit.
+    let calibration = Calibration::default_path().and_then(|path| Calibration::load(&path)).ok().flatten();
+    let eta = calibration.filter(|_| budget.is_none()).and_then(|calibration| committer.eta(&calibration));
+    if let Some(eta) = eta {
+        println!("Expected to take {}, and 99% of the time {}.", roughly(eta.expected), within(eta.p99));
+        if let Some(limit) = committer.warn_after().filter(|&limit| eta.median > limit) {
+            eprintln!(
+                "Warning: this search will probably take longer than {} (median {}, 90% {}). \
+                 Ctrl-C stops it, and --resume picks it up again.",
+                format_duration(limit),
+                format_duration(eta.median),
+                within(eta.p90),
+            );
+        }
+    } else if let Some(limit) = committer.warn_after().filter(|_| budget.is_none()) {
+        eprintln!(
+            "Warning: can't tell whether this search will take longer than {} without a measured \
+             hashrate. Run gitchain bench to measure one.",
+            format_duration(limit),
+        );
+    }
+    let hash = committer.commit()?;
+    match committer.retries() {
+        0 => {}
+        1 => println!("HEAD or the index changed while mining, so the commit was mined again on top of them."),
+        retries => println!("HEAD or the index changed while mining, so the commit was mined {} more times.", retries),
+    }
+    match budget {
+        Some(budget) => println!("Committed {}, the best hash found within {}.", hash, format_duration(budget)),
+        None => println!("Successfully committed {} with desired target.", hash),
+    }
+    Ok(hash)
+}
+
+fn estimate(
+    predicate: &dyn HashPredicate,
+    hashrate: Option<f64>,
+    backend: Option<String>,
+    threads: Option<usize>,
+) -> Result<(), Error> {
+    let estimate = Estimate::for_predicate(predicate).ok_or(EstimateError::UnknownProbabilityError {})?;
+    println!("Expected attempts: {:.0}", estimate.expected_attempts());
+    println!("Median attempts:   {:.0}", estimate.attempts_within(0.5));
+
+    let hashrate = match hashrate {
+        Some(hashrate) => Some(hashrate),
+        None => {
+            let backend = backend::select(backend.as_deref())?;
+            let threads = workers(threads, false).threads()?;
+            let calibration = Calibration::load(&Calibration::default_path()?)?;
+            calibration.and_then(|calibration| calibration.hashrate(backend.name(), threads))
+        }
+    };
+
+    match hashrate.and_then(|hashrate| estimate.eta(hashrate).map(|eta| (hashrate, eta))) {
+        Some((hashrate, eta)) => {
+            println!();
+            println!("At {}:", format_hashrate(hashrate));
+            println!("  expected  {}", roughly(eta.expected));
+            println!("  median    {}", roughly(eta.median));
+            println!("  90%       {}", within(eta.p90));
+            println!("  99%       {}", within(eta.p99));
+        }
+        None => println!("Run gitchain bench, or pass --hashrate, to see how long that takes."),
+    }
     Ok(())
 }
 
+fn roughly(duration: Duration) -> String {
+    if duration.as_secs() == 0 {
+        "less than a second".to_string()
+    } else {
+        format!("about {}", format_duration(duration))
+    }
+}
+
+fn within(duration: Duration) -> String {
+    if duration.as_secs() == 0 {
+        "within a second".to_string()
+    } else {
+        format!("within {}", format_duration(duration))
+    }
+}
+
+fn bench(threads: Option<usize>) -> Result<(), Error> {
+    let max = workers(threads, false).threads()?;
+    let mut calibration = Calibration::default();
+    for backend in backend::all().into_iter().filter(|backend| backend.is_available() && backend.self_test()) {
+        for threads in calibration::thread_counts(max) {
+            let measurement = calibration::measure(backend.clone(), threads, calibration::MEASURE_TIME)?;
+            println!(
+                "{:<8} {:>3} {:<7} {:>12}",
+                measurement.backend,
+                measurement.threads,
+                threads_noun(measurement.threads),
+                format_hashrate(measurement.hashrate),
+            );
+            calibration.measurements.push(measurement);
+        }
+    }
+
+    if let Some(best) = calibration.best() {
+        println!("Fastest is {} with {} {}.", best.backend, best.threads, threads_noun(best.threads));
+    }
+    let path = Calibration::default_path()?;
+    calibration.save(&path)?;
+    println!("Saved calibration to {}.", path.display());
+    Ok(())
+}
+
+fn threads_noun(threads: usize) -> &'static str {
+    if threads == 1 { "thread" } else { "threads" }
+}
+
+fn message(msg: Vec<String>, file: Option<PathBuf>) -> Result<Message, Error> {
+    let text = match file {
+        Some(ref path) if path.as_os_str() == "-" => {
+            let mut text = String::new();
+            io::stdin().read_to_string(&mut text)
+                .map_err(|_| IoError::ReadStdinError {})?;
+            text
+        }
+        Some(path) => fs::read_to_string(&path)
+            .map_err(|_| IoError::ReadFileError { path: path.display().to_string() })?,
+        None if msg.is_empty() => return Ok(Message::Edit),
+        None => message::join_paragraphs(&msg),
+    };
+
+    Ok(Message::Given(text))
+}
+
+fn prefix_set(prefixes: Option<String>, prefix_file: Option<PathBuf>) -> Result<Option<Arc<PrefixSet>>, Error> {
+    if prefixes.is_none() && prefix_file.is_none() {
+        return Ok(None);
+    }
+
+    let mut words = prefixes.unwrap_or_default().replace(',', "\n");
+    if let Some(path) = prefix_file {
+        let contents = fs::read_to_string(&path)
+            .map_err(|_| IoError::ReadFileError { path: path.display().to_string() })?;
+        words.push('\n');
+        words.push_str(&contents);
+    }
+
+    Ok(Some(Arc::new(PrefixSet::parse(&words)?)))
+}
+
+fn predicate(
+    prefix: Option<String>,
+    pattern: Option<String>,
+    bits: Option<u32>,
+    threshold: Option<String>,
+) -> Result<Box<dyn HashPredicate>, Error> {
+    if let Some(pattern) = pattern {
+        return Ok(Box::new(Pattern::parse(&pattern)?));
+    }
+    if let Some(bits) = bits {
+        return Ok(Box::new(Target::leading_zero_bits(bits)?));
+    }
+    if let Some(threshold) = threshold {
+        return Ok(Box::new(Target::threshold(&threshold)?));
+    }
+
+    let prefix = prefix.as_deref().unwrap_or(DEFAULT_PREFIX);
+    Ok(Box::new(Pattern::prefix(prefix)?))
+}
+
+fn workers(threads: Option<usize>, pin_threads: bool) -> Workers {
+    let mut workers = Workers::new();
+    if let Some(threads) = threads {
+        workers = workers.with_threads(threads);
+    }
+    if pin_threads {
+        workers = workers.pinned();
+    }
+    workers
+}
+
 fn add(path: String) -> Result<(), Error> {
     Command::new("git")
-        .args(&["add", &path])
+        .args(["add", &path])
         .output()
         .map_err(|_| GitTerminalError::AddError {})?;
 
@@ -0,0 +1,146 @@
+use crate::errors::LockError;
+use failure::Error;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process;
+
+/// File inside `.git` that a running gitchain holds, so two of them never commit to the same
+/// repository at once.
+pub const LOCK_FILE: &str = "gitchain.lock";
+
+/// Holds the lock on a repository until it is dropped.  On unix the lock is an advisory lock on
+/// the open file, which the system lets go of if the process holding it is killed, so a lock file
+/// left behind is simply taken over.  The file records the id of the process holding it.
+#[derive(Debug)]
+pub struct RepoLock {
+    path: PathBuf,
+    _file: File,
+}
+
+impl RepoLock {
+    /// Takes the lock on the repository at `git_dir`, failing if another gitchain that is still
+    /// running holds it.
+    #[cfg(unix)]
+    pub fn acquire(git_dir: &Path) -> Result<RepoLock, Error> {
+        let path = git_dir.join(LOCK_FILE);
+        let failed = || LockError::CreateError { path: path.display().to_string() };
+
+        loop {
+            // Not truncated until it is locked, so the holder's id stays readable.
+            let mut file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(&path)
+                .map_err(|_| failed())?;
+            if !try_lock(&file).map_err(|_| failed())? {
+                return Err(LockError::HeldError { path: path.display().to_string() }.into());
+            }
+            // The last holder removes the file before letting go of it, so a lock taken on a file
+            // that is no longer at `path` is one nobody else can see.  Open it again.
+            if !is_at(&file, &path) {
+                continue;
+            }
+
+            file.set_len(0)
+                .and_then(|_| write!(file, "{}", process::id()))
+                .map_err(|_| failed())?;
+            return Ok(RepoLock { path, _file: file });
+        }
+    }
+
+    /// Takes the lock on the repository at `git_dir`, failing if the lock file exists.
+    #[cfg(not(unix))]
+    pub fn acquire(git_dir: &Path) -> Result<RepoLock, Error> {
+        use std::io::ErrorKind;
+
+        let path = git_dir.join(LOCK_FILE);
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(mut file) => {
+                write!(file, "{}", process::id())
+                    .map_err(|_| LockError::CreateError { path: path.display().to_string() })?;
+                Ok(RepoLock { path, _file: file })
+            }
+            Err(ref error) if error.kind() == ErrorKind::AlreadyExists => {
+                Err(LockError::HeldError { path: path.display().to_string() }.into())
+            }
+            Err(_) => Err(LockError::CreateError { path: path.display().to_string() }.into()),
+        }
+    }
+}
+
+impl Drop for RepoLock {
+    /// Removes the lock file while still holding the lock, so nobody can take a lock on it that
+    /// the next gitchain would not see.
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Tries to lock `file` without waiting, returning whether it was locked.
+#[cfg(unix)]
+fn try_lock(file: &File) -> std::io::Result<bool> {
+    use std::os::unix::io::AsRawFd;
+
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } == 0 {
+        return Ok(true);
+    }
+    let error = std::io::Error::last_os_error();
+    match error.raw_os_error() {
+        Some(code) if code == libc::EWOULDBLOCK => Ok(false),
+        _ => Err(error),
+    }
+}
+
+/// Whether the open `file` is still the one at `path`.
+#[cfg(unix)]
+fn is_at(file: &File, path: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    match (file.metadata(), fs::metadata(path)) {
+        (Ok(open), Ok(current)) => open.dev() == current.dev() && open.ino() == current.ino(),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_lock_is_exclusive() -> Result<(), Error> {
+        let dir = TempDir::new()?;
+        let lock = RepoLock::acquire(dir.path())?;
+        assert!(RepoLock::acquire(dir.path()).is_err());
+
+        drop(lock);
+        assert!(!dir.path().join(LOCK_FILE).exists());
+        RepoLock::acquire(dir.path())?;
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_taking_over_a_stale_lock() -> Result<(), Error> {
+        let dir = TempDir::new()?;
+        // Left behind by a gitchain that was killed, so nothing holds it.
+        fs::write(dir.path().join(LOCK_FILE), "2147483646")?;
+
+        let _lock = RepoLock::acquire(dir.path())?;
+        assert_eq!(fs::read_to_string(dir.path().join(LOCK_FILE))?, process::id().to_string());
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_lock_on_a_removed_file_is_not_held() -> Result<(), Error> {
+        let dir = TempDir::new()?;
+        let path = dir.path().join(LOCK_FILE);
+        let removed = File::create(&path)?;
+        fs::remove_file(&path)?;
+        assert!(try_lock(&removed)?);
+        assert!(!is_at(&removed, &path));
+
+        let lock = RepoLock::acquire(dir.path())?;
+        assert!(is_at(&lock._file, &path));
+        Ok(())
+    }
+}
@@ -0,0 +1,261 @@
+use crate::errors::MessageError;
+use crate::hooks::Hooks;
+use failure::Error;
+use git2::{Repository, Status, StatusOptions};
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+/// File inside `.git` that the commit message is written to, for the editor and for the hooks
+/// that read or rewrite it, as `git commit` does.
+pub const MESSAGE_FILE: &str = "COMMIT_EDITMSG";
+
+/// Git config key naming the editor commit messages are written in.
+pub const EDITOR_KEY: &str = "core.editor";
+
+/// Git config key naming a file the editor starts the commit message from.
+pub const TEMPLATE_KEY: &str = "commit.template";
+
+/// Where the message of a new commit comes from.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Message {
+    /// A message given up front, with `-m` or `-F`.
+    Given(String),
+    /// A message written in the editor, starting from `commit.template` if there is one.
+    Edit,
+}
+
+/// Works out the message of a new commit the way `git commit` does: writes it to
+/// `COMMIT_EDITMSG`, runs the prepare-commit-msg hook, opens the editor if the message is to be
+/// edited, cleans the message up and runs the commit-msg hook.  Fails on an empty message, so
+/// nothing is mined for a commit that would be aborted.
+pub fn prepare(repo: &Repository, message: Message, hooks: &Hooks) -> Result<String, Error> {
+    let path = repo.path().join(MESSAGE_FILE);
+    let (contents, source, template) = match message {
+        Message::Given(text) => (format!("{}\n", text), Some("message"), None),
+        Message::Edit => {
+            let template = read_template(repo)?;
+            let source = template.as_ref().map(|_| "template");
+            let contents = format!("{}\n{}", template.as_deref().unwrap_or(""), status_comment(repo));
+            (contents, source, Some(template.unwrap_or_default()))
+        }
+    };
+    let edit = template.is_some();
+
+    write(&path, &contents)?;
+    hooks.prepare_commit_msg(&path, source)?;
+    if edit {
+        run_editor(repo, &path)?;
+    }
+
+    let msg = cleanup(&read(&path)?, edit);
+    if msg.is_empty() {
+        return Err(MessageError::EmptyError {}.into());
+    }
+    if template.is_some_and(|template| !template.trim().is_empty() && cleanup(&template, true) == msg) {
+        return Err(MessageError::UneditedTemplateError {}.into());
+    }
+
+    write(&path, &format!("{}\n", msg))?;
+    hooks.commit_msg(&path)?;
+
+    let msg = cleanup(&read(&path)?, edit);
+    if msg.is_empty() {
+        return Err(MessageError::EmptyError {}.into());
+    }
+    Ok(msg)
+}
+
+/// Cleans up a commit message like git's default cleanup: strips trailing whitespace from every
+/// line, blank lines from the start and end, and runs of blank lines down to one.  Comment lines,
+/// starting with `#`, go too when `strip_comments` is set, as they do for an edited message.
+pub fn cleanup(message: &str, strip_comments: bool) -> String {
+    let mut lines: Vec<&str> = Vec::new();
+    for line in message.lines() {
+        if strip_comments && line.starts_with('#') {
+            continue;
+        }
+        let line = line.trim_end();
+        if line.is_empty() && lines.last().is_none_or(|last| last.is_empty()) {
+            continue;
+        }
+        lines.push(line);
+    }
+    if lines.last() == Some(&"") {
+        lines.pop();
+    }
+
+    lines.join("\n")
+}
+
+/// Joins the messages given with `-m`, each as a paragraph of its own.
+pub fn join_paragraphs(paragraphs: &[String]) -> String {
+    paragraphs.join("\n\n")
+}
+
+fn read_template(repo: &Repository) -> Result<Option<String>, Error> {
+    let path = match repo.config().ok().and_then(|config| config.get_path(TEMPLATE_KEY).ok()) {
+        Some(path) => path,
+        None => return Ok(None),
+    };
+
+    let template = fs::read_to_string(&path)
+        .map_err(|_| MessageError::TemplateError { path: path.display().to_string() })?;
+    Ok(Some(template))
+}
+
+/// The editor git would use: `GIT_EDITOR`, `core.editor`, `VISUAL`, `EDITOR`, or else vi.
+fn editor(repo: &Repository) -> String {
+    env::var("GIT_EDITOR").ok()
+        .or_else(|| repo.config().ok().and_then(|config| config.get_string(EDITOR_KEY).ok()))
+        .or_else(|| env::var("VISUAL").ok())
+        .or_else(|| env::var("EDITOR").ok())
+        .filter(|editor| !editor.trim().is_empty())
+        .unwrap_or_else(|| "vi".to_string())
+}
+
+/// Opens the message in the editor through the shell, as git does, so the editor can be given
+/// with arguments of its own.
+fn run_editor(repo: &Repository, path: &Path) -> Result<(), Error> {
+    let editor = editor(repo);
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$@\"", editor))
+        .arg(&editor)
+        .arg(path)
+        .status()
+        .map_err(|_| MessageError::EditorError { editor: editor.clone() })?;
+
+    if !status.success() {
+        return Err(MessageError::EditorError { editor }.into());
+    }
+    Ok(())
+}
+
+/// The comment git puts under a message being edited, saying how to abort and what is about to
+/// be committed.
+fn status_comment(repo: &Repository) -> String {
+    let mut lines = vec![
+        "Please enter the commit message for your changes. Lines starting".to_string(),
+        "with '#' will be ignored, and an empty message aborts the commit.".to_string(),
+        String::new(),
+    ];
+
+    lines.push(match repo.head() {
+        Ok(head) if repo.head_detached().unwrap_or(false) => {
+            let id = head.target().map(|oid| oid.to_string()).unwrap_or_default();
+            format!("HEAD detached at {}", &id[..id.len().min(7)])
+        }
+        Ok(head) => format!("On branch {}", head.shorthand().unwrap_or("HEAD")),
+        Err(_) => {
+            let branch = repo.find_reference("HEAD").ok()
+                .and_then(|head| head.symbolic_target().map(|target| target.trim_start_matches("refs/heads/").to_string()))
+                .unwrap_or_default();
+            format!("On branch {}\n\nNo commits yet", branch)
+        }
+    });
+
+    let mut staged = Vec::new();
+    let mut unstaged = Vec::new();
+    let mut untracked = Vec::new();
+    let mut options = StatusOptions::new();
+    options.include_untracked(true);
+    if let Ok(statuses) = repo.statuses(Some(&mut options)) {
+        for entry in statuses.iter() {
+            let status = entry.status();
+            let path = entry.path().unwrap_or("").to_string();
+            if let Some(change) = staged_change(status) {
+                let path = match entry.head_to_index().and_then(|delta| delta.old_file().path().map(Path::to_path_buf)) {
+                    Some(old) if status.contains(Status::INDEX_RENAMED) => format!("{} -> {}", old.display(), path),
+                    _ => path.clone(),
+                };
+                staged.push(format!("\t{:<12}{}", change, path));
+            }
+            if let Some(change) = unstaged_change(status) {
+                unstaged.push(format!("\t{:<12}{}", change, path));
+            }
+            if status.contains(Status::WT_NEW) {
+                untracked.push(format!("\t{}", path));
+            }
+        }
+    }
+
+    for (heading, entries) in [
+        ("Changes to be committed:", staged),
+        ("Changes not staged for commit:", unstaged),
+        ("Untracked files:", untracked),
+    ] {
+        if !entries.is_empty() {
+            lines.push(heading.to_string());
+            lines.extend(entries);
+            lines.push(String::new());
+        }
+    }
+
+    lines.iter()
+        .flat_map(|line| line.split('\n'))
+        .map(|line| match line {
+            "" => "#\n".to_string(),
+            line if line.starts_with('\t') => format!("#{}\n", line),
+            line => format!("# {}\n", line),
+        })
+        .collect()
+}
+
+fn staged_change(status: Status) -> Option<&'static str> {
+    if status.contains(Status::INDEX_NEW) {
+        Some("new file:")
+    } else if status.contains(Status::INDEX_MODIFIED) {
+        Some("modified:")
+    } else if status.contains(Status::INDEX_DELETED) {
+        Some("deleted:")
+    } else if status.contains(Status::INDEX_RENAMED) {
+        Some("renamed:")
+    } else if status.contains(Status::INDEX_TYPECHANGE) {
+        Some("typechange:")
+    } else {
+        None
+    }
+}
+
+fn unstaged_change(status: Status) -> Option<&'static str> {
+    if status.contains(Status::WT_MODIFIED) {
+        Some("modified:")
+    } else if status.contains(Status::WT_DELETED) {
+        Some("deleted:")
+    } else if status.contains(Status::WT_TYPECHANGE) {
+        Some("typechange:")
+    } else {
+        None
+    }
+}
+
+fn write(path: &Path, contents: &str) -> Result<(), Error> {
+    fs::write(path, contents)
+        .map_err(|_| MessageError::MessageFileError { path: path.display().to_string() }.into())
+}
+
+fn read(path: &Path) -> Result<String, Error> {
+    fs::read_to_string(path)
+        .map_err(|_| MessageError::MessageFileError { path: path.display().to_string() }.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cleanup() {
+        let message = "\n\nSubject  \n\n\n\nBody line\t\n# a comment\n\n";
+        assert_eq!(cleanup(message, false), "Subject\n\nBody line\n# a comment");
+        assert_eq!(cleanup(message, true), "Subject\n\nBody line");
+        assert_eq!(cleanup("# only a comment\n\n", true), "");
+    }
+
+    #[test]
+    fn test_joining_paragraphs() {
+        let paragraphs = vec!["Subject".to_string(), "Body".to_string()];
+        assert_eq!(join_paragraphs(&paragraphs), "Subject\n\nBody");
+    }
+}
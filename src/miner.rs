@@ -1,58 +1,525 @@
-use crate::errors::MiningError;
-use crate::hasher;
+use crate::backend::{self, HashBackend};
+use crate::calibration::Calibration;
+use crate::cancel::CancellationToken;
+use crate::checkpoint::{Journal, Space};
+use crate::errors::{MiningError, WorkerError};
+use crate::estimate::{Estimate, Eta};
+use crate::hasher::{self, Midstate, DIGEST_LEN};
+use crate::jitter::Jitter;
+use crate::nonce::{self, NonceEncoding};
+use crate::progress::{MiningObserver, Stats, REPORT_INTERVAL};
+use crate::target::HashPredicate;
+use crate::throttle::{self, CpuBudget};
 use crate::writer;
 use failure::Error;
 use rayon::prelude::*;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Number of nonces a worker tries, reusing the same buffers, before picking up more work.
+const CHUNK_SIZE: u64 = 1 << 16;
+
+/// Smallest chance of the jitter window holding a solution for which it is searched at all.
+/// Below it, the nonce is almost certainly needed anyway, so it may as well be tried straight away.
+/// Searches against the clock still try the window, as their best hash can come from it.
+const MIN_JITTER_CHANCE: f64 = 0.01;
 
 /// Miner carries out the work of mining for a correct nonce, that when appended to the commit causes
-/// the resulting commit hash to have the desired prefix.
+/// the resulting commit hash satisfy the desired predicate.
 pub struct Miner {
-    prefix: String,
+    predicate: Box<dyn HashPredicate>,
     blob: String,
-    max_nonce: u32,
+    nonces_per_epoch: u64,
+    backend: OnceLock<Arc<dyn HashBackend>>,
+    encoding: Arc<dyn NonceEncoding>,
+    jitter: Option<Jitter>,
+    observer: Option<Arc<dyn MiningObserver>>,
+    report_interval: Duration,
+    journal: Option<Arc<Journal>>,
+    pool: Option<Arc<ThreadPool>>,
+    cpu_budget: Option<CpuBudget>,
+    background: bool,
+    deterministic: bool,
+}
+
+/// The state shared by every worker for one call to `solve`.
+struct Run<'a> {
+    stats: &'a Stats,
+    cancel: &'a CancellationToken,
+    best: Option<&'a Mutex<Option<Best>>>,
+}
+
+/// The best scoring candidate a search has tried so far, kept when mining for a limited time.
+#[derive(Clone, Copy, Debug)]
+struct Best {
+    score: u32,
+    space: Space,
+    candidate: u64,
+}
+
+/// The layout of the commit for one value of the extra nonce, the high 32 bits of the nonce.
+/// Every attempt within an epoch shares its midstate and only varies the low 32 bits.
+struct Epoch {
+    extra_nonce: u64,
+    padding: usize,
+    midstate: Midstate,
 }
 
 impl Miner {
     /// Used to create a new instance of a miner, which should always be associated with
     /// a Committer.  In this sense a Miner always works for a Committer and if the Committer
     /// is cleaned up, the Miner should be as well.
-    pub fn new(prefix: String, blob: String) -> Miner {
-        let max_nonce = u32::max_value();
-
+    pub fn new<P: HashPredicate + 'static>(predicate: P, blob: String) -> Miner {
         Miner {
-            prefix,
+            predicate: Box::new(predicate),
             blob,
-            max_nonce,
+            nonces_per_epoch: 1 << 32,
+            backend: OnceLock::new(),
+            encoding: Arc::new(nonce::HexLine),
+            jitter: None,
+            observer: None,
+            report_interval: REPORT_INTERVAL,
+            journal: None,
+            pool: None,
+            cpu_budget: None,
+            background: false,
+            deterministic: false,
         }
     }
 
+    /// Mines with the given SHA-1 backend instead of the one detected for this CPU.
+    pub fn with_backend(mut self, backend: Arc<dyn HashBackend>) -> Miner {
+        self.backend = OnceLock::from(backend);
+        self
+    }
+
+    /// Encodes the nonce into the commit with the given encoding instead of on a line of its own.
+    pub fn with_encoding(mut self, encoding: Arc<dyn NonceEncoding>) -> Miner {
+        self.encoding = encoding;
+        self
+    }
+
+    /// Searches the author and committer timestamps within `jitter` before falling back to a
+    /// nonce, so the message is left untouched whenever the window holds a solution.
+    pub fn with_jitter(mut self, jitter: Jitter) -> Miner {
+        self.jitter = Some(jitter);
+        self
+    }
+
+    /// Reports progress to `observer` every report interval while solving.
+    pub fn with_observer(mut self, observer: Arc<dyn MiningObserver>) -> Miner {
+        self.observer = Some(observer);
+        self
+    }
+
+    /// Reports progress this often instead of every second.
+    pub fn with_report_interval(mut self, interval: Duration) -> Miner {
+        self.report_interval = interval;
+        self
+    }
+
+    /// Records every chunk of candidates searched in `journal`, and skips the ones it says have
+    /// already been searched, so a long search can be picked up again after it is stopped.
+    pub fn with_journal(mut self, journal: Arc<Journal>) -> Miner {
+        self.journal = Some(journal);
+        self
+    }
+
+    /// Runs the workers on `pool` instead of rayon's global pool, which has a thread for every
+    /// logical CPU.
+    pub fn with_thread_pool(mut self, pool: Arc<ThreadPool>) -> Miner {
+        self.pool = Some(pool);
+        self
+    }
+
+    /// Has each worker sleep between chunks of work, keeping its average CPU use near `budget`.
+    pub fn with_cpu_budget(mut self, budget: CpuBudget) -> Miner {
+        self.cpu_budget = Some(budget);
+        self
+    }
+
+    /// Lowers the scheduling priority of every worker thread, so mining gives way to anything else
+    /// that wants the CPU.  This lasts for the life of the threads, so without `with_thread_pool`
+    /// the miner builds a pool of its own for every solve rather than lower rayon's global one.
+    pub fn in_background(mut self) -> Miner {
+        self.background = true;
+        self
+    }
+
+    /// Always solves with the lowest nonce, or the first combination of timestamps, that works,
+    /// instead of the first any worker happens to find.  The same commit then mines to the same
+    /// result on every run and every machine, whatever the number of threads or the backend.
+    pub fn deterministic(mut self) -> Miner {
+        self.deterministic = true;
+        self
+    }
+
+    /// Mines `blob` from scratch on the next solve, recording its progress in `journal` instead,
+    /// for when the commit has to be rebuilt on top of a repository that changed.
+    pub fn start_over(&mut self, blob: String, journal: Option<Arc<Journal>>) {
+        self.blob = blob;
+        self.journal = journal;
+    }
+
+    /// How long solving is expected to take on this machine, going by the hashrate `calibration`
+    /// measured for this miner's backend and thread count, slowed down by any CPU budget.
+    /// Returns `None` without a measurement, or for a predicate of unknown probability.
+    pub fn eta(&self, calibration: &Calibration) -> Option<Eta> {
+        let estimate = Estimate::for_predicate(self.predicate.as_ref())?;
+        let mut hashrate = calibration.hashrate(self.backend().name(), self.threads())?;
+        if let Some(budget) = self.cpu_budget {
+            hashrate *= budget.percent() / 100.0;
+        }
+
+        estimate.eta(hashrate)
+    }
+
+    /// Hashes a commit with this miner's backend, as `hasher::hash_blob` does.
+    pub fn hash_blob(&self, blob: &str) -> String {
+        hasher::hash_blob_with(self.backend().as_ref(), blob)
+    }
+
+    /// The backend given with `with_backend`, or else the one detected for this CPU.  Detecting
+    /// runs every backend's self-test, so it waits until the backend is first needed.
+    fn backend(&self) -> &Arc<dyn HashBackend> {
+        self.backend.get_or_init(backend::detect)
+    }
+
+    fn threads(&self) -> usize {
+        self.pool.as_ref().map_or_else(rayon::current_num_threads, |pool| pool.current_num_threads())
+    }
+
     /// Solve method will attempt to find a nonce (random value) that when applied to the commit
-    /// causes it's hash to have the desired prefix. This is carried out using a thread pool and a
+    /// causes it's hash to satisfy the desired predicate. This is carried out using a thread pool and a
     /// succeed fast strategy. If successful it will return a tuple of the blob and successful hash.
+    ///
+    /// Once every value of the low 32 bits of the nonce has been tried, the extra nonce in the high
+    /// 32 bits is bumped and the search carries on, so a predicate that can be satisfied at all
+    /// will be.
+    ///
+    /// With a jitter window, every combination of timestamps in it is tried first, and a nonce is
+    /// only added to the commit if none of them work.
     pub fn solve(&mut self) -> Result<(String, String), Error> {
-        let winning_nonce = self.find_correct_nonce()?;
-        let blob = writer::append_nonce_to_blob(&self.blob, winning_nonce);
-        let hash = hasher::hash_blob(&blob);
+        self.solve_cancellable(&CancellationToken::new())
+    }
+
+    /// Solves like `solve`, but stops with `MiningError::CancelledError` or
+    /// `MiningError::TimedOutError` as soon as the workers notice `cancel` has been cancelled.
+    pub fn solve_cancellable(&mut self, cancel: &CancellationToken) -> Result<(String, String), Error> {
+        let blob = self.run(cancel, None, None)?;
+        let hash = self.hash_blob(&blob);
 
-        return Ok((blob, hash));
+        Ok((blob, hash))
     }
 
-    fn find_correct_nonce(&mut self) -> Result<u32, Error> {
-        let result = (0..self.max_nonce).into_par_iter().find_any(|nonce| {
-            let blob = writer::append_nonce_to_blob(&self.blob, *nonce);
-            let hash = hasher::hash_blob(&blob);
-            hash.starts_with(&self.prefix)
-        });
+    /// Solves like `solve_cancellable`, but stops looking for a match after `budget` and returns
+    /// the commit whose hash scored best by then instead of failing, as it does if every
+    /// candidate runs out first.  Workers compare the score of every hash they try, which costs a
+    /// little speed.  Cancelling `cancel` still stops it with an error.
+    pub fn solve_within(&mut self, budget: Duration, cancel: &CancellationToken) -> Result<(String, String), Error> {
+        let best = Mutex::new(None);
+        let blob = match self.run(&cancel.clone().with_timeout(budget), Some(&best), Some(budget)) {
+            Err(error) if matches!(
+                error.downcast_ref::<MiningError>(),
+                Some(MiningError::TimedOutError {}) | Some(MiningError::SolveError {})
+            ) => {
+                let best = best.into_inner().unwrap_or_else(|poisoned| poisoned.into_inner());
+                best.and_then(|best| self.candidate_blob(best)).ok_or(error)?
+            }
+            solved => solved?,
+        };
+        let hash = self.hash_blob(&blob);
+
+        Ok((blob, hash))
+    }
+
+    fn run(
+        &self,
+        cancel: &CancellationToken,
+        best: Option<&Mutex<Option<Best>>>,
+        budget: Option<Duration>,
+    ) -> Result<String, Error> {
+        let stats = Stats::new(self.threads()).with_budget(budget);
+        let run = Run { stats: &stats, cancel, best };
+        let pool = match &self.pool {
+            Some(pool) => Some(pool.clone()),
+            None if self.background => Some(Miner::background_pool()?),
+            None => None,
+        };
+        self.observe(&stats, || match &pool {
+            Some(pool) => pool.install(|| self.find_solution(&run)),
+            None => self.find_solution(&run),
+        })
+    }
+
+    /// A pool as large as rayon's global one, for workers whose priority is about to be lowered.
+    fn background_pool() -> Result<Arc<ThreadPool>, Error> {
+        let threads = rayon::current_num_threads();
+        let pool = ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .map_err(|_| WorkerError::PoolError { threads })?;
+
+        Ok(Arc::new(pool))
+    }
+
+    /// Rebuilds the commit a candidate in the search space stands for.
+    fn candidate_blob(&self, best: Best) -> Option<String> {
+        match best.space {
+            Space::Timestamps => {
+                let stamps = self.jitter.as_ref().and_then(|jitter| jitter.stamps(&self.blob))?;
+                Some(stamps.apply(&self.blob, best.candidate))
+            }
+            Space::Nonces => {
+                let first_nonce = best.candidate >> 32 << 32;
+                let padding = nonce::aligned_padding(self.encoding.as_ref(), &self.blob, first_nonce);
+                Some(self.encoding.encode(&self.blob, best.candidate, padding).content)
+            }
+        }
+    }
+
+    fn find_solution(&self, run: &Run) -> Result<String, Error> {
+        if let Some(blob) = self.find_correct_timestamps(run)? {
+            return Ok(blob);
+        }
+
+        let (winning_nonce, padding) = self.find_correct_nonce(run)?;
+        Ok(self.encoding.encode(&self.blob, winning_nonce, padding).content)
+    }
+
+    /// Runs `search`, reporting `stats` to the observer from a separate thread until it returns.
+    /// An observer that panics is not reported to again, but the search carries on without it.
+    fn observe<T>(&self, stats: &Stats, search: impl FnOnce() -> T) -> T {
+        let observer = match &self.observer {
+            Some(observer) => observer.as_ref(),
+            None => return search(),
+        };
+        let probability = self.predicate.probability();
+        let interval = self.report_interval;
+        let (done, finished) = mpsc::channel::<()>();
+
+        thread::scope(|scope| {
+            scope.spawn(move || {
+                let _ = panic::catch_unwind(AssertUnwindSafe(|| {
+                    while let Err(RecvTimeoutError::Timeout) = finished.recv_timeout(interval) {
+                        observer.on_progress(&stats.snapshot(probability));
+                    }
+                    observer.on_finish(&stats.snapshot(probability));
+                }));
+            });
+
+            let result = search();
+            drop(done);
+            result
+        })
+    }
+
+    fn find_correct_timestamps(&self, run: &Run) -> Result<Option<String>, Error> {
+        let stamps = match self.jitter.as_ref().and_then(|jitter| jitter.stamps(&self.blob)) {
+            Some(stamps) => stamps,
+            None => return Ok(None),
+        };
+        let chance = self.predicate.probability().map(|probability| stamps.candidates() as f64 * probability);
+        if run.best.is_none() && chance.is_some_and(|chance| chance < MIN_JITTER_CHANCE) {
+            return Ok(None);
+        }
+
+        let full = writer::prepend_header_to_blob(&self.blob);
+        let start = full.len() - self.blob.len() + stamps.offset();
+        let end = start + stamps.len();
+        let midstate = Midstate::new(&full.as_bytes()[..start], stamps.len(), &full.as_bytes()[end..]);
+
+        let candidate = self.search(run, Space::Timestamps, 0, &midstate, stamps.candidates(), |buffer, candidate| {
+            stamps.write(buffer, candidate)
+        })?;
+        Ok(candidate.map(|candidate| stamps.apply(&self.blob, candidate)))
+    }
+
+    fn find_correct_nonce(&self, run: &Run) -> Result<(u64, usize), Error> {
+        for extra_nonce in 0..=u64::from(u32::MAX) {
+            let epoch = self.epoch(extra_nonce);
+            if let Some(nonce) = self.search_epoch(run, &epoch)? {
+                return Ok((epoch.extra_nonce << 32 | nonce, epoch.padding));
+            }
+        }
 
-        let winning_nonce = result.ok_or(MiningError::SolveError {})?;
+        Err(MiningError::SolveError {}.into())
+    }
+
+    fn epoch(&self, extra_nonce: u64) -> Epoch {
+        let first_nonce = extra_nonce << 32;
+        let padding = nonce::aligned_padding(self.encoding.as_ref(), &self.blob, first_nonce);
+        let layout = self.encoding.encode(&self.blob, first_nonce, padding);
+
+        let full = writer::prepend_header_to_blob(&layout.content);
+        let varying_start = full.len() - layout.content.len() + layout.varying_offset;
+        let varying_end = varying_start + self.encoding.varying_len();
+
+        Epoch {
+            extra_nonce,
+            padding,
+            midstate: Midstate::new(
+                &full.as_bytes()[..varying_start],
+                self.encoding.varying_len(),
+                &full.as_bytes()[varying_end..],
+            ),
+        }
+    }
 
-        Ok(winning_nonce)
+    fn search_epoch(&self, run: &Run, epoch: &Epoch) -> Result<Option<u64>, Error> {
+        let base = epoch.extra_nonce << 32;
+        self.search(run, Space::Nonces, base, &epoch.midstate, self.nonces_per_epoch, |buffer, nonce| {
+            self.encoding.write_varying(buffer, nonce as u32)
+        })
+    }
+
+    /// Searches candidates `0..count` in parallel, where `write` writes the varying bytes of a
+    /// candidate into the front of a buffer.  Workers check for cancellation before each chunk,
+    /// and the first to see it stops the whole search.  In the journal, these candidates are
+    /// numbered from `base` in `space`.
+    fn search<W>(
+        &self,
+        run: &Run,
+        space: Space,
+        base: u64,
+        midstate: &Midstate,
+        count: u64,
+        write: W,
+    ) -> Result<Option<u64>, Error>
+    where
+        W: Fn(&mut [u8], u64) + Sync,
+    {
+        let chunks = count.div_ceil(CHUNK_SIZE);
+
+        let visit = |chunk: u64| {
+            if let Err(error) = run.cancel.check() {
+                return Some(Err(error));
+            }
+
+            let start = chunk * CHUNK_SIZE;
+            let end = (start + CHUNK_SIZE).min(count);
+            let journal = self.journal.as_ref();
+            if journal.is_some_and(|journal| journal.is_done(space, base + start, base + end)) {
+                return None;
+            }
+
+            if self.background {
+                throttle::lower_priority();
+            }
+
+            let started = Instant::now();
+            let found = self.search_chunk(run, space, base, midstate, start, end, &write);
+            if found.is_none() {
+                if let Some(journal) = journal {
+                    journal.complete(space, base + start, base + end);
+                }
+                if let Some(budget) = &self.cpu_budget {
+                    thread::sleep(budget.pause_after(started.elapsed()));
+                }
+            }
+            found.map(Ok)
+        };
+
+        // Taking the first chunk in order with a match, rather than whichever is found first,
+        // costs waiting for the chunks before it to finish.
+        let stopped = if self.deterministic {
+            (0..chunks).into_par_iter().find_map_first(visit)
+        } else {
+            (0..chunks).into_par_iter().find_map_any(visit)
+        };
+        stopped.transpose()
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn search_chunk<W>(
+        &self,
+        run: &Run,
+        space: Space,
+        base: u64,
+        midstate: &Midstate,
+        start: u64,
+        end: u64,
+        write: &W,
+    ) -> Option<u64>
+    where
+        W: Fn(&mut [u8], u64),
+    {
+        let lanes = self.backend().lanes();
+        let mut buffers = vec![midstate.tail_buffer(); lanes];
+        let mut digests = vec![[0u8; DIGEST_LEN]; lanes];
+        let mut best = [0xff; DIGEST_LEN];
+        let mut best_score: Option<(u32, u64)> = None;
+        let offset = midstate.tail_offset();
+
+        let mut nonce = start;
+        let mut found = None;
+        while nonce < end {
+            let count = ((end - nonce) as usize).min(lanes);
+            for (lane, buffer) in buffers.iter_mut().enumerate() {
+                let lane_nonce = nonce + lane.min(count - 1) as u64;
+                write(&mut buffer[offset..], lane_nonce);
+            }
+
+            midstate.finish(self.backend().as_ref(), &buffers, &mut digests);
+            for (lane, digest) in digests[..count].iter().enumerate() {
+                if *digest < best {
+                    best = *digest;
+                }
+                if run.best.is_some() {
+                    let score = self.predicate.score(digest);
+                    if best_score.is_none_or(|(best_score, _)| score > best_score) {
+                        best_score = Some((score, nonce + lane as u64));
+                    }
+                }
+            }
+            nonce += count as u64;
+
+            if let Some(lane) = digests[..count].iter().position(|digest| self.predicate.matches(digest)) {
+                found = Some(nonce - count as u64 + lane as u64);
+                break;
+            }
+        }
+
+        run.stats.record(nonce - start, &best);
+        if let (Some(shared), Some((score, candidate))) = (run.best, best_score) {
+            let mut shared = shared.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            if shared.is_none_or(|shared| score > shared.score) {
+                *shared = Some(Best { score, space, candidate: base + candidate });
+            }
+        }
+        found
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::calibration::Measurement;
+    use crate::pattern::Pattern;
+    use crate::progress::Progress;
+    use crate::target::Target;
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    #[derive(Default)]
+    struct Recorder {
+        updates: Mutex<Vec<Progress>>,
+        finished: Mutex<Vec<Progress>>,
+    }
+
+    impl MiningObserver for Recorder {
+        fn on_progress(&self, progress: &Progress) {
+            self.updates.lock().unwrap().push(progress.clone());
+        }
+
+        fn on_finish(&self, progress: &Progress) {
+            self.finished.lock().unwrap().push(progress.clone());
+        }
+    }
 
     #[test]
     fn test_miner_finds_a_correct_nonce() -> Result<(), Error> {
@@ -61,10 +528,356 @@ mod tests {
                     author AuthorTest <test@test.com> 1454691142 -0000\n\
                     committer AuthorTest <test@test.com> 1454691142 -0000\n\n\
                     MessageTest";
-        let prefix = "0000".to_string();
-        let mut miner = Miner::new(prefix, blob.to_string());
+        let mut miner = Miner::new(Target::prefix("0000")?, blob.to_string());
         let (_, hash) = miner.solve()?;
         assert!(hash.starts_with("0000"));
         Ok(())
     }
+
+    #[test]
+    fn test_miner_solution_hashes_correctly_with_long_message() -> Result<(), Error> {
+        let blob = format!("tree TreeTest\n\
+                            author AuthorTest <test@test.com> 1454691142 -0000\n\
+                            committer AuthorTest <test@test.com> 1454691142 -0000\n\n\
+                            {}", "MessageTest ".repeat(100));
+        let mut miner = Miner::new(Target::prefix("000")?, blob);
+        let (blob, hash) = miner.solve()?;
+        assert_eq!(hasher::hash_blob(&blob), hash);
+        assert!(hash.starts_with("000"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_every_available_backend_solves() -> Result<(), Error> {
+        let blob = "tree TreeTest\n\
+                    author AuthorTest <test@test.com> 1454691142 -0000\n\
+                    committer AuthorTest <test@test.com> 1454691142 -0000\n\n\
+                    MessageTest";
+        for backend in backend::all().into_iter().filter(|backend| backend.is_available()) {
+            let name = backend.name().to_string();
+            let mut miner = Miner::new(Target::prefix("000")?, blob.to_string()).with_backend(backend);
+            let (blob, hash) = miner.solve()?;
+            assert_eq!(hasher::hash_blob(&blob), hash, "{} backend", name);
+            assert!(hash.starts_with("000"), "{} backend", name);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_miner_finds_leading_zero_bits() -> Result<(), Error> {
+        let blob = "tree TreeTest\n\n\
+                    MessageTest";
+        let mut miner = Miner::new(Target::leading_zero_bits(13)?, blob.to_string());
+        let (_, hash) = miner.solve()?;
+        let fourth_digit = u8::from_str_radix(&hash[3..4], 16)?;
+        assert!(hash.starts_with("000") && fourth_digit < 8);
+        Ok(())
+    }
+
+    #[test]
+    fn test_miner_finds_pattern() -> Result<(), Error> {
+        let blob = "tree TreeTest\n\n\
+                    MessageTest";
+        let mut miner = Miner::new(Pattern::parse("#,suffix:?a")?, blob.to_string());
+        let (_, hash) = miner.solve()?;
+        assert!(hash.as_bytes()[0].is_ascii_digit());
+        assert!(hash.ends_with('a'));
+        Ok(())
+    }
+
+    #[test]
+    fn test_miner_rolls_over_the_extra_nonce() -> Result<(), Error> {
+        let blob = "tree TreeTest\n\n\
+                    MessageTest";
+        let mut miner = Miner::new(Target::prefix("000")?, blob.to_string());
+        miner.nonces_per_epoch = 16;
+
+        let (blob, hash) = miner.solve()?;
+        let nonce_line = blob.lines().last().unwrap_or("");
+        let nonce = u64::from_str_radix(nonce_line, 16)?;
+        assert!(nonce >> 32 > 0);
+        assert!(nonce & 0xffff_ffff < 16);
+        assert_eq!(hasher::hash_blob(&blob), hash);
+        assert!(hash.starts_with("000"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_every_nonce_encoding_solves() -> Result<(), Error> {
+        let blob = "tree TreeTest\n\n\
+                    MessageTest";
+        for encoding in nonce::all() {
+            let name = encoding.name().to_string();
+            let mut miner = Miner::new(Target::prefix("000")?, blob.to_string()).with_encoding(encoding);
+            miner.nonces_per_epoch = 1 << 10;
+            let (mined, hash) = miner.solve()?;
+            assert!(mined.contains("MessageTest"), "{} encoding", name);
+            assert_eq!(hasher::hash_blob(&mined), hash, "{} encoding", name);
+            assert!(hash.starts_with("000"), "{} encoding", name);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_miner_varies_timestamps_instead_of_the_message() -> Result<(), Error> {
+        let blob = "tree TreeTest\n\
+                    author AuthorTest <test@test.com> 1454691142 -0000\n\
+                    committer AuthorTest <test@test.com> 1454691142 -0000\n\n\
+                    MessageTest";
+        let jitter = Jitter::new(Duration::from_secs(3600))?;
+        let mut miner = Miner::new(Target::prefix("000")?, blob.to_string()).with_jitter(jitter);
+        let (mined, hash) = miner.solve()?;
+        assert!(mined.ends_with("\n\nMessageTest"));
+        assert_ne!(mined, blob);
+        assert_eq!(hasher::hash_blob(&mined), hash);
+        assert!(hash.starts_with("000"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_miner_falls_back_to_a_nonce_when_the_window_is_too_small() -> Result<(), Error> {
+        let blob = "tree TreeTest\n\
+                    author AuthorTest <test@test.com> 1454691142 -0000\n\
+                    committer AuthorTest <test@test.com> 1454691142 -0000\n\n\
+                    MessageTest";
+        let jitter = Jitter::new(Duration::from_secs(1))?;
+        let mut miner = Miner::new(Target::prefix("0000")?, blob.to_string()).with_jitter(jitter);
+        let (mined, hash) = miner.solve()?;
+        assert!(mined.starts_with(blob));
+        assert_eq!(hasher::hash_blob(&mined), hash);
+        assert!(hash.starts_with("0000"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_miner_reports_progress_to_its_observer() -> Result<(), Error> {
+        let blob = "tree TreeTest\n\n\
+                    MessageTest";
+        let recorder = Arc::new(Recorder::default());
+        let mut miner = Miner::new(Target::prefix("0000")?, blob.to_string())
+            .with_observer(recorder.clone())
+            .with_report_interval(Duration::from_millis(1));
+        let (_, hash) = miner.solve()?;
+
+        let finished = recorder.finished.lock().unwrap();
+        assert_eq!(finished.len(), 1);
+        assert!(finished[0].attempts() > 0);
+        assert_eq!(finished[0].probability, Some(1.0 / 65536.0));
+        assert!(finished[0].best_hash.as_ref().is_some_and(|best| best.starts_with("0000") && best <= &hash));
+
+        let updates = recorder.updates.lock().unwrap();
+        assert!(updates.windows(2).all(|pair| pair[0].attempts() <= pair[1].attempts()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_miner_outlives_a_panicking_observer() -> Result<(), Error> {
+        struct Panicking;
+
+        impl MiningObserver for Panicking {
+            fn on_progress(&self, _progress: &Progress) {
+                panic!("observer failed");
+            }
+        }
+
+        let blob = "tree TreeTest\n\n\
+                    MessageTest";
+        let mut miner = Miner::new(Target::prefix("0000")?, blob.to_string())
+            .with_observer(Arc::new(Panicking))
+            .with_report_interval(Duration::from_millis(1));
+        let (_, hash) = miner.solve()?;
+        assert!(hash.starts_with("0000"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_solve_within_reports_the_time_left() -> Result<(), Error> {
+        let blob = "tree TreeTest\n\n\
+                    MessageTest";
+        let budget = Duration::from_millis(200);
+        let recorder = Arc::new(Recorder::default());
+        let mut miner = Miner::new(Target::leading_zero_bits(160)?, blob.to_string())
+            .with_observer(recorder.clone())
+            .with_report_interval(Duration::from_millis(1));
+        let (_, hash) = miner.solve_within(budget, &CancellationToken::new())?;
+        assert!(hash.starts_with("000"));
+
+        let updates = recorder.updates.lock().unwrap();
+        assert!(!updates.is_empty());
+        assert!(updates.iter().all(|progress| progress.budget == Some(budget)));
+        assert!(updates.iter().all(|progress| progress.eta().is_some_and(|eta| eta <= budget)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_miner_stops_when_cancelled() -> Result<(), Error> {
+        let blob = "tree TreeTest\n\n\
+                    MessageTest";
+        let mut miner = Miner::new(Target::prefix(&"0".repeat(40))?, blob.to_string());
+
+        let cancel = CancellationToken::new();
+        let canceller = cancel.clone();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            canceller.cancel();
+        });
+        let error = miner.solve_cancellable(&cancel).unwrap_err();
+        assert!(matches!(error.downcast_ref::<MiningError>(), Some(MiningError::CancelledError {})));
+
+        let timeout = CancellationToken::new().with_timeout(Duration::from_millis(50));
+        let error = miner.solve_cancellable(&timeout).unwrap_err();
+        assert!(matches!(error.downcast_ref::<MiningError>(), Some(MiningError::TimedOutError {})));
+        Ok(())
+    }
+
+    #[test]
+    fn test_miner_skips_what_the_journal_has_done() -> Result<(), Error> {
+        use crate::checkpoint::Checkpoint;
+
+        let blob = "tree TreeTest\n\n\
+                    MessageTest";
+        let dir = tempfile::TempDir::new()?;
+        let checkpoint = Checkpoint::new(blob.to_string(), "prefix 000".to_string(), "hex".to_string(), None);
+
+        let journal = Arc::new(Journal::new(dir.path(), checkpoint.clone()));
+        let mut miner = Miner::new(Target::prefix("000")?, blob.to_string()).with_journal(journal.clone());
+        let (first, _) = miner.solve()?;
+        let first_nonce = u64::from_str_radix(first.lines().last().unwrap_or(""), 16)?;
+
+        let skipped = Arc::new(Journal::new(dir.path(), checkpoint));
+        skipped.complete(Space::Nonces, 0, CHUNK_SIZE * (first_nonce / CHUNK_SIZE + 1));
+        let mut miner = Miner::new(Target::prefix("000")?, blob.to_string()).with_journal(skipped.clone());
+        let (second, hash) = miner.solve()?;
+        let second_nonce = u64::from_str_radix(second.lines().last().unwrap_or(""), 16)?;
+
+        assert!(second_nonce >= CHUNK_SIZE * (first_nonce / CHUNK_SIZE + 1));
+        assert!(hash.starts_with("000"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_miner_runs_on_its_own_thread_pool() -> Result<(), Error> {
+        let blob = "tree TreeTest\n\n\
+                    MessageTest";
+        let pool = Arc::new(rayon::ThreadPoolBuilder::new().num_threads(2).build()?);
+        let recorder = Arc::new(Recorder::default());
+        let mut miner = Miner::new(Target::prefix("000")?, blob.to_string())
+            .with_thread_pool(pool)
+            .with_observer(recorder.clone());
+        let (_, hash) = miner.solve()?;
+
+        assert!(hash.starts_with("000"));
+        assert_eq!(recorder.finished.lock().unwrap()[0].thread_attempts.len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_miner_solves_within_a_cpu_budget_in_the_background() -> Result<(), Error> {
+        let blob = "tree TreeTest\n\n\
+                    MessageTest";
+        let pool = Arc::new(rayon::ThreadPoolBuilder::new().num_threads(1).build()?);
+        let mut miner = Miner::new(Target::prefix("00")?, blob.to_string())
+            .with_thread_pool(pool)
+            .with_cpu_budget(CpuBudget::from_percent(50.0).unwrap())
+            .in_background();
+        let (mined, hash) = miner.solve()?;
+
+        assert_eq!(hasher::hash_blob(&mined), hash);
+        assert!(hash.starts_with("00"));
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_background_miner_leaves_the_global_pool_alone() -> Result<(), Error> {
+        let blob = "tree TreeTest\n\n\
+                    MessageTest";
+        let mut miner = Miner::new(Target::prefix("00")?, blob.to_string()).in_background();
+        miner.solve()?;
+
+        let schedulers: Vec<i32> = rayon::broadcast(|_| unsafe { libc::sched_getscheduler(0) });
+        assert!(schedulers.iter().all(|&scheduler| scheduler != libc::SCHED_IDLE));
+        Ok(())
+    }
+
+    #[test]
+    fn test_eta_from_calibration() -> Result<(), Error> {
+        let blob = "tree TreeTest\n\n\
+                    MessageTest";
+        let calibration = Calibration {
+            measurements: vec![Measurement { backend: "scalar".to_string(), threads: 1, hashrate: 4096.0 }],
+        };
+        let pool = Arc::new(rayon::ThreadPoolBuilder::new().num_threads(2).build()?);
+        let miner = Miner::new(Target::prefix("000")?, blob.to_string())
+            .with_backend(backend::find("scalar")?)
+            .with_thread_pool(pool);
+        assert_eq!(miner.eta(&calibration).map(|eta| eta.expected), Some(Duration::from_millis(500)));
+
+        let throttled = miner.with_cpu_budget(CpuBudget::from_percent(50.0).unwrap());
+        assert_eq!(throttled.eta(&calibration).map(|eta| eta.expected), Some(Duration::from_secs(1)));
+
+        let other = Miner::new(Target::prefix("000")?, blob.to_string()).with_backend(backend::find("scalar")?);
+        assert_eq!(other.eta(&Calibration::default()), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_solve_within_commits_the_best_hash_found() -> Result<(), Error> {
+        let blob = "tree TreeTest\n\n\
+                    MessageTest";
+        let mut miner = Miner::new(Target::leading_zero_bits(160)?, blob.to_string());
+        let (mined, hash) = miner.solve_within(Duration::from_millis(200), &CancellationToken::new())?;
+
+        assert_eq!(hasher::hash_blob(&mined), hash);
+        assert!(hash.starts_with("000"));
+
+        let mut easy = Miner::new(Target::prefix("00")?, blob.to_string());
+        let (_, hash) = easy.solve_within(Duration::from_secs(3600), &CancellationToken::new())?;
+        assert!(hash.starts_with("00"));
+
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+        assert!(miner.solve_within(Duration::from_secs(1), &cancel).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_solve_within_keeps_jittered_timestamps() -> Result<(), Error> {
+        let blob = "tree TreeTest\n\
+                    author AuthorTest <test@test.com> 1454691142 -0000\n\
+                    committer AuthorTest <test@test.com> 1454691142 -0000\n\n\
+                    MessageTest";
+        let mut miner = Miner::new(Pattern::parse("suffix:0000000000")?, blob.to_string())
+            .with_jitter(Jitter::new(Duration::from_secs(3600))?);
+        let (mined, hash) = miner.solve_within(Duration::from_millis(100), &CancellationToken::new())?;
+
+        assert_eq!(hasher::hash_blob(&mined), hash);
+        assert!(mined.ends_with("MessageTest"));
+        assert!(hash.ends_with("0"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_deterministic_miner_finds_the_lowest_nonce() -> Result<(), Error> {
+        let blob = "tree TreeTest\n\n\
+                    MessageTest";
+        let mut solutions = Vec::new();
+        for threads in [1, 3] {
+            let pool = Arc::new(rayon::ThreadPoolBuilder::new().num_threads(threads).build()?);
+            let mut miner = Miner::new(Target::prefix("000")?, blob.to_string())
+                .with_thread_pool(pool)
+                .deterministic();
+            solutions.push(miner.solve()?);
+        }
+        assert_eq!(solutions[0], solutions[1]);
+
+        let (mined, _) = &solutions[0];
+        let lowest = u64::from_str_radix(mined.lines().last().unwrap_or(""), 16)?;
+        let padding = nonce::aligned_padding(&nonce::HexLine, blob, 0);
+        for smaller in 0..lowest {
+            let candidate = nonce::HexLine.encode(blob, smaller, padding).content;
+            assert!(!hasher::hash_blob(&candidate).starts_with("000"));
+        }
+        Ok(())
+    }
 }
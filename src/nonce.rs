@@ -0,0 +1,278 @@
+use crate::errors::NonceError;
+use crate::writer::{self, NONCE_DIGITS};
+use failure::Error;
+use std::sync::Arc;
+
+/// Header added to the commit by the `header` encoding.  git keeps headers it doesn't know about,
+/// but `git log` only shows them with `--pretty=raw`.
+pub const NONCE_HEADER: &str = "gitchain-nonce";
+
+/// Trailer added to the commit message by the `trailer` encoding.
+pub const NONCE_TRAILER: &str = "Gitchain-Nonce";
+
+/// A commit with a nonce encoded into it.
+pub struct Layout {
+    /// The full commit content, without the `commit <len>\0` header.
+    pub content: String,
+    /// Offset within `content` of the bytes holding the low 32 bits of the nonce.
+    pub varying_offset: usize,
+}
+
+/// A strategy for where and how a nonce is written into a commit.  The low 32 bits of the nonce
+/// change on every attempt, so an encoding writes them as a fixed number of bytes the miner can
+/// overwrite in place.  The high 32 bits, the extra nonce, only change between epochs.
+pub trait NonceEncoding: Send + Sync {
+    /// The name used to pick this encoding with `--nonce-style`.
+    fn name(&self) -> &str;
+
+    /// Number of bytes the low 32 bits of a nonce are written as.
+    fn varying_len(&self) -> usize;
+
+    /// Writes the low 32 bits of a nonce into the front of `buffer`, exactly as `encode` would,
+    /// without allocating.
+    fn write_varying(&self, buffer: &mut [u8], nonce: u32);
+
+    /// Encodes `nonce` into `blob`.  `padding` asks for that much constant filler in front of the
+    /// varying bytes, which the miner uses to line them up with the end of a SHA-1 block.
+    fn encode(&self, blob: &str, nonce: u64, padding: usize) -> Layout;
+}
+
+/// Picks the padding for `nonce` that leaves the fewest 64 byte blocks to compress from the
+/// first varying byte to the end of the object.  When nothing follows the varying bytes this is
+/// always a single block, no matter how long the message is.
+pub fn aligned_padding(encoding: &dyn NonceEncoding, blob: &str, nonce: u64) -> usize {
+    let blocks_to_compress = |padding: usize| {
+        let layout = encoding.encode(blob, nonce, padding);
+        let header_len = writer::prepend_header_to_blob(&layout.content).len() - layout.content.len();
+        let first_block = (header_len + layout.varying_offset) / 64;
+        let last_block = (header_len + layout.content.len() + 8) / 64;
+        last_block - first_block
+    };
+
+    (0..64).min_by_key(|&padding| blocks_to_compress(padding)).unwrap_or(0)
+}
+
+/// The original encoding, the nonce in hex on its own line at the end of the message.
+pub struct HexLine;
+
+impl NonceEncoding for HexLine {
+    fn name(&self) -> &str {
+        "hex"
+    }
+
+    fn varying_len(&self) -> usize {
+        NONCE_DIGITS
+    }
+
+    fn write_varying(&self, buffer: &mut [u8], nonce: u32) {
+        writer::write_nonce_digits(buffer, nonce);
+    }
+
+    fn encode(&self, blob: &str, nonce: u64, padding: usize) -> Layout {
+        let width = writer::min_nonce_width(nonce) + padding;
+        let content = writer::append_padded_nonce_to_blob(blob, nonce, width);
+        let varying_offset = content.len() - NONCE_DIGITS;
+
+        Layout { content, varying_offset }
+    }
+}
+
+/// The nonce in hex as a `Gitchain-Nonce:` trailer, joining the message's existing trailers if it
+/// ends with some.
+pub struct Trailer;
+
+impl NonceEncoding for Trailer {
+    fn name(&self) -> &str {
+        "trailer"
+    }
+
+    fn varying_len(&self) -> usize {
+        NONCE_DIGITS
+    }
+
+    fn write_varying(&self, buffer: &mut [u8], nonce: u32) {
+        writer::write_nonce_digits(buffer, nonce);
+    }
+
+    fn encode(&self, blob: &str, nonce: u64, padding: usize) -> Layout {
+        let width = writer::min_nonce_width(nonce) + padding;
+        let separator = if ends_with_trailers(blob) { "\n" } else { "\n\n" };
+        let content = format!("{}{}{}: {:0width$x}", blob, separator, NONCE_TRAILER, nonce, width = width);
+        let varying_offset = content.len() - NONCE_DIGITS;
+
+        Layout { content, varying_offset }
+    }
+}
+
+/// The nonce in binary as trailing spaces (0) and tabs (1) at the end of the message, which
+/// `git log` prints but nobody can see.
+pub struct Whitespace;
+
+impl Whitespace {
+    fn write_bits(buffer: &mut [u8], bits: u32) {
+        for (i, byte) in buffer[..32].iter_mut().enumerate() {
+            *byte = if bits >> (31 - i) & 1 == 1 { b'\t' } else { b' ' };
+        }
+    }
+}
+
+impl NonceEncoding for Whitespace {
+    fn name(&self) -> &str {
+        "whitespace"
+    }
+
+    fn varying_len(&self) -> usize {
+        32
+    }
+
+    fn write_varying(&self, buffer: &mut [u8], nonce: u32) {
+        Whitespace::write_bits(buffer, nonce);
+    }
+
+    fn encode(&self, blob: &str, nonce: u64, padding: usize) -> Layout {
+        let mut bytes = blob.as_bytes().to_vec();
+        bytes.resize(bytes.len() + padding, b' ');
+
+        let extra_nonce = (nonce >> 32) as u32;
+        if extra_nonce > 0 {
+            bytes.resize(bytes.len() + 32, 0);
+            let start = bytes.len() - 32;
+            Whitespace::write_bits(&mut bytes[start..], extra_nonce);
+        }
+
+        let varying_offset = bytes.len();
+        bytes.resize(bytes.len() + 32, 0);
+        Whitespace::write_bits(&mut bytes[varying_offset..], nonce as u32);
+
+        let content = String::from_utf8(bytes).unwrap_or_default();
+        Layout { content, varying_offset }
+    }
+}
+
+/// The nonce in hex as an extra `gitchain-nonce` header after the committer, leaving the message
+/// untouched.  Everything after the header has to be rehashed on each attempt, so this gets slower
+/// as the message gets longer.
+pub struct Header;
+
+impl NonceEncoding for Header {
+    fn name(&self) -> &str {
+        "header"
+    }
+
+    fn varying_len(&self) -> usize {
+        NONCE_DIGITS
+    }
+
+    fn write_varying(&self, buffer: &mut [u8], nonce: u32) {
+        writer::write_nonce_digits(buffer, nonce);
+    }
+
+    fn encode(&self, blob: &str, nonce: u64, padding: usize) -> Layout {
+        let (headers, message) = match blob.find("\n\n") {
+            Some(end) => (&blob[..end], &blob[end..]),
+            None => (blob, ""),
+        };
+
+        let width = writer::min_nonce_width(nonce) + padding;
+        let nonce_line = format!("\n{} {:0width$x}", NONCE_HEADER, nonce, width = width);
+        let varying_offset = headers.len() + nonce_line.len() - NONCE_DIGITS;
+        let content = format!("{}{}{}", headers, nonce_line, message);
+
+        Layout { content, varying_offset }
+    }
+}
+
+/// Every built-in encoding.  The first is the default.
+pub fn all() -> Vec<Arc<dyn NonceEncoding>> {
+    vec![Arc::new(HexLine), Arc::new(Trailer), Arc::new(Whitespace), Arc::new(Header)]
+}
+
+/// Looks up a built-in encoding by name, or the default one if no name is given.
+pub fn select(name: Option<&str>) -> Result<Arc<dyn NonceEncoding>, Error> {
+    let name = name.unwrap_or("hex");
+    let encoding = all().into_iter()
+        .find(|encoding| encoding.name() == name)
+        .ok_or(NonceError::UnknownStyleError { name: name.to_string() })?;
+
+    Ok(encoding)
+}
+
+fn ends_with_trailers(blob: &str) -> bool {
+    let message = blob.find("\n\n").map(|end| &blob[end + 2..]).unwrap_or("");
+    let last_paragraph = match message.rfind("\n\n") {
+        Some(start) => &message[start + 2..],
+        None => return false,
+    };
+
+    !last_paragraph.is_empty() && last_paragraph.lines().all(|line| {
+        line.find(": ").is_some_and(|colon| {
+            let token = &line[..colon];
+            !token.is_empty() && token.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hasher;
+
+    const BLOB: &str = "tree TreeTest\n\
+                        author AuthorTest <test@test.com> 1454691142 -0000\n\
+                        committer AuthorTest <test@test.com> 1454691142 -0000\n\n\
+                        MessageTest";
+
+    #[test]
+    fn test_encodings_write_the_same_varying_bytes_as_encode() {
+        for encoding in all() {
+            for &nonce in &[0, 0x1234_5678, 0x2_0000_0001] {
+                let layout = encoding.encode(BLOB, nonce, 3);
+                let mut written = layout.content.clone().into_bytes();
+                let range = layout.varying_offset..layout.varying_offset + encoding.varying_len();
+                encoding.write_varying(&mut written[range.clone()], !(nonce as u32));
+                encoding.write_varying(&mut written[range], nonce as u32);
+                assert_eq!(written, layout.content.as_bytes(), "{} encoding", encoding.name());
+            }
+        }
+    }
+
+    #[test]
+    fn test_aligned_padding_keeps_trailing_nonces_in_one_block() {
+        for encoding in all().into_iter().filter(|encoding| encoding.name() != "header") {
+            for message_len in 0..150 {
+                let blob = format!("{}{}", BLOB, "m".repeat(message_len));
+                let padding = aligned_padding(encoding.as_ref(), &blob, 1 << 32);
+                let layout = encoding.encode(&blob, 1 << 32, padding);
+                let full = writer::prepend_header_to_blob(&layout.content);
+                let start = full.len() - layout.content.len() + layout.varying_offset;
+                assert_eq!(start / 64, (full.len() + 8) / 64, "{} encoding", encoding.name());
+            }
+        }
+    }
+
+    #[test]
+    fn test_encodings_keep_the_message_readable() {
+        let hex = HexLine.encode(BLOB, 0x2a, 0).content;
+        assert!(hex.ends_with("MessageTest\n0000002a"));
+
+        let trailer = Trailer.encode(BLOB, 0x2a, 0).content;
+        assert!(trailer.ends_with("MessageTest\n\nGitchain-Nonce: 0000002a"));
+        let signed = format!("{}\n\nSigned-off-by: AuthorTest <test@test.com>", BLOB);
+        let trailer = Trailer.encode(&signed, 0x2a, 0).content;
+        assert!(trailer.ends_with("test.com>\nGitchain-Nonce: 0000002a"));
+
+        let whitespace = Whitespace.encode(BLOB, 0x2a, 0).content;
+        assert_eq!(whitespace.trim_end(), BLOB);
+
+        let header = Header.encode(BLOB, 0x2a, 0).content;
+        assert!(header.contains("-0000\ngitchain-nonce 0000002a\n\nMessageTest"));
+        assert_ne!(hasher::hash_blob(&header), hasher::hash_blob(BLOB));
+    }
+
+    #[test]
+    fn test_unknown_nonce_style_is_rejected() {
+        assert_eq!(select(None).unwrap().name(), "hex");
+        assert_eq!(select(Some("trailer")).unwrap().name(), "trailer");
+        assert!(select(Some("emoji")).is_err());
+    }
+}
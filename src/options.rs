@@ -1,6 +1,18 @@
 extern crate structopt;
 use structopt::StructOpt;
 use std::path::PathBuf;
+use std::time::Duration;
+use crate::cancel::CancellationToken;
+use crate::clock::Clock;
+use crate::errors::OptionsError;
+use crate::identity::Identity;
+use crate::jitter::Jitter;
+use crate::message::Message;
+use crate::progress::MiningObserver;
+use std::sync::Arc;
+use crate::target::HashPredicate;
+use crate::throttle::CpuBudget;
+use crate::workers::Workers;
 
 #[derive(StructOpt, Debug)]
 #[structopt(
@@ -8,6 +20,7 @@ name = "gitchain",
 about = "The custom git commit hash prefixer",
 )]
 /// You can use gitchain to create a git commit with a git hash that is prefixed with zeros.
+#[allow(clippy::large_enum_variant)]
 pub enum Opts {
     #[structopt(name = "commit")]
     /// Git commits with a custom hash prefix.
@@ -16,13 +29,179 @@ pub enum Opts {
         #[structopt(short = "r", long = "repository", parse(from_os_str), default_value = ".")]
         repo: PathBuf,
 
-        /// Message flag allows you to provide a commit message.
-        #[structopt(short = "m", long = "message")]
-        msg: String,
+        /// Message flag allows you to provide a commit message. Given more than once, each is a paragraph of its own. Without it, the editor is opened.
+        #[structopt(short = "m", long = "message", raw(number_of_values = "1"))]
+        msg: Vec<String>,
 
-        /// Pass in a custom prefix for the git hash.
-        #[structopt(short = "p", long = "prefix", default_value = "000000")]
-        prefix: String,
+        /// Take the commit message from this file, or from stdin if it is `-`.
+        #[structopt(short = "F", long = "file", parse(from_os_str), conflicts_with = "msg")]
+        file: Option<PathBuf>,
+
+        /// Pass in a custom prefix for the git hash, `?` matches any digit. Defaults to 000000.
+        #[structopt(short = "p", long = "prefix")]
+        prefix: Option<String>,
+
+        /// Accept a git hash starting with any of these comma separated prefixes, such as `cafe,beef,c0ffee`.
+        #[structopt(long = "prefixes", conflicts_with = "prefix")]
+        prefixes: Option<String>,
+
+        /// Accept a git hash starting with any of the prefixes in this file, one per line.
+        #[structopt(long = "prefix-file", parse(from_os_str), conflicts_with = "prefix")]
+        prefix_file: Option<PathBuf>,
+
+        /// Match the git hash against a pattern, such as `prefix:00??ff,suffix:####` or `contains:c0ffee`.
+        #[structopt(long = "pattern", conflicts_with = "prefix", conflicts_with = "prefixes", conflicts_with = "prefix_file")]
+        pattern: Option<String>,
+
+        /// Require at least this many leading zero bits in the git hash, instead of a prefix.
+        #[structopt(
+            long = "bits",
+            conflicts_with = "prefix",
+            conflicts_with = "prefixes",
+            conflicts_with = "prefix_file",
+            conflicts_with = "pattern"
+        )]
+        bits: Option<u32>,
+
+        /// Require the git hash, read as a 160 bit number, to be less than this 40 digit hex target.
+        #[structopt(
+            long = "target",
+            conflicts_with = "prefix",
+            conflicts_with = "prefixes",
+            conflicts_with = "prefix_file",
+            conflicts_with = "pattern",
+            conflicts_with = "bits"
+        )]
+        threshold: Option<String>,
+
+        /// Force a SHA-1 backend (sha-ni, avx2 or scalar) instead of detecting the fastest one.
+        #[structopt(long = "backend")]
+        backend: Option<String>,
+
+        /// How the nonce is written into the commit: hex (own line), trailer, whitespace or header.
+        #[structopt(long = "nonce-style")]
+        nonce_style: Option<String>,
+
+        /// Mine by moving the commit timestamps up to this far either way, such as `90s` or `10m`, before adding a nonce.
+        #[structopt(long = "jitter", parse(try_from_str = "parse_duration"))]
+        jitter: Option<Duration>,
+
+        /// Let --jitter pick any timezone for the commit timestamps as well.
+        #[structopt(long = "jitter-timezones", requires = "jitter")]
+        jitter_timezones: bool,
+
+        /// Give up mining after this long, such as `30s` or `2h`, without committing anything.
+        #[structopt(long = "timeout", parse(try_from_str = "parse_duration"))]
+        timeout: Option<Duration>,
+
+        /// Mine for this long, such as `30s`, then commit the best hash found. Without a target, the one with the most leading zeros.
+        #[structopt(long = "within", parse(try_from_str = "parse_duration"), conflicts_with = "timeout", conflicts_with = "resume")]
+        within: Option<Duration>,
+
+        /// Always commit with the lowest nonce that works, so the same commit mines the same way on every machine.
+        #[structopt(long = "deterministic", conflicts_with = "within")]
+        deterministic: bool,
+
+        /// Pick up a search for the same commit where it was stopped, instead of starting afresh.
+        #[structopt(long = "resume")]
+        resume: bool,
+
+        /// If HEAD moves or the index changes while mining, mine the commit again on top of them instead of giving up.
+        #[structopt(long = "retry")]
+        retry: bool,
+
+        /// Skip the pre-commit and commit-msg hooks.
+        #[structopt(short = "n", long = "no-verify")]
+        no_verify: bool,
+
+        /// Number of mining threads. Defaults to GITCHAIN_THREADS, or the CPUs available to this process.
+        #[structopt(short = "j", long = "threads")]
+        threads: Option<usize>,
+
+        /// Pin each mining thread to a core of its own.
+        #[structopt(long = "pin-threads")]
+        pin_threads: bool,
+
+        /// Keep each mining thread's average CPU use near this share, such as `50%`.
+        #[structopt(long = "cpu-budget", parse(try_from_str = "parse_cpu_budget"))]
+        cpu_budget: Option<CpuBudget>,
+
+        /// Mine at the lowest scheduling priority, so everything else runs first. Only has an effect on Linux.
+        #[structopt(long = "background")]
+        background: bool,
+
+        /// Warn before a search that will probably take longer than this, such as `10m`. Defaults to the gitchain.warnAfter git config.
+        #[structopt(long = "warn-after", parse(try_from_str = "parse_duration"))]
+        warn_after: Option<Duration>,
+    },
+
+    #[structopt(name = "estimate")]
+    /// Estimates how many attempts and how long mining a git hash will take.
+    Estimate {
+        /// The prefix to estimate for, `?` matches any digit. Defaults to 000000.
+        #[structopt(short = "p", long = "prefix")]
+        prefix: Option<String>,
+
+        /// Estimate for a git hash starting with any of these comma separated prefixes.
+        #[structopt(long = "prefixes", conflicts_with = "prefix")]
+        prefixes: Option<String>,
+
+        /// Estimate for a git hash starting with any of the prefixes in this file, one per line.
+        #[structopt(long = "prefix-file", parse(from_os_str), conflicts_with = "prefix")]
+        prefix_file: Option<PathBuf>,
+
+        /// Estimate for a git hash matching a pattern, such as `prefix:00??ff,suffix:####`.
+        #[structopt(long = "pattern", conflicts_with = "prefix", conflicts_with = "prefixes", conflicts_with = "prefix_file")]
+        pattern: Option<String>,
+
+        /// Estimate for at least this many leading zero bits in the git hash.
+        #[structopt(
+            long = "bits",
+            conflicts_with = "prefix",
+            conflicts_with = "prefixes",
+            conflicts_with = "prefix_file",
+            conflicts_with = "pattern"
+        )]
+        bits: Option<u32>,
+
+        /// Estimate for a git hash less than this 40 digit hex target.
+        #[structopt(
+            long = "target",
+            conflicts_with = "prefix",
+            conflicts_with = "prefixes",
+            conflicts_with = "prefix_file",
+            conflicts_with = "pattern",
+            conflicts_with = "bits"
+        )]
+        threshold: Option<String>,
+
+        /// Hashes per second to estimate times with, such as `12.5M`. Defaults to the one measured by `gitchain bench`.
+        #[structopt(long = "hashrate", parse(try_from_str = "parse_hashrate"))]
+        hashrate: Option<f64>,
+
+        /// SHA-1 backend whose measured hashrate to use, instead of the fastest one detected.
+        #[structopt(long = "backend")]
+        backend: Option<String>,
+
+        /// Number of mining threads whose measured hashrate to use.
+        #[structopt(short = "j", long = "threads")]
+        threads: Option<usize>,
+    },
+
+    #[structopt(name = "bench")]
+    /// Measures how fast each SHA-1 backend mines here, so commits can say how long they will take.
+    Bench {
+        /// Measure up to this many threads. Defaults to GITCHAIN_THREADS, or the CPUs available to this process.
+        #[structopt(short = "j", long = "threads")]
+        threads: Option<usize>,
+    },
+
+    #[structopt(name = "undo")]
+    /// Undoes the last commit gitchain made, staging its changes again.
+    Undo {
+        /// Provide a path to the base directory of your github repository.
+        #[structopt(short = "r", long = "repository", parse(from_os_str), default_value = ".")]
+        repo: PathBuf,
     },
 
     #[structopt(name = "add")]
@@ -36,6 +215,104 @@ pub enum Opts {
 /// For ease of use in the application as a translation from commands
 pub struct Options {
     pub repo: PathBuf,
-    pub msg: String,
-    pub prefix: String,
+    pub msg: Message,
+    pub predicate: Box<dyn HashPredicate>,
+    pub backend: Option<String>,
+    pub nonce_style: Option<String>,
+    pub jitter: Option<Jitter>,
+    pub observer: Option<Arc<dyn MiningObserver>>,
+    pub clock: Option<Arc<dyn Clock>>,
+    pub identity: Option<Arc<dyn Identity>>,
+    pub cancel: CancellationToken,
+    pub timeout: Option<Duration>,
+    pub resume: bool,
+    pub retry: bool,
+    pub no_verify: bool,
+    pub within: Option<Duration>,
+    pub deterministic: bool,
+    pub workers: Workers,
+    pub cpu_budget: Option<CpuBudget>,
+    pub background: bool,
+    pub warn_after: Option<Duration>,
+}
+
+/// Parses a duration given as a number followed by `s`, `m` or `h`.  A bare number is seconds.
+pub fn parse_duration(duration: &str) -> Result<Duration, OptionsError> {
+    let invalid = || OptionsError::InvalidDurationError { duration: duration.to_string() };
+    let (number, unit) = match duration.find(|c: char| !c.is_ascii_digit()) {
+        Some(index) => duration.split_at(index),
+        None => (duration, "s"),
+    };
+
+    let seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        _ => return Err(invalid()),
+    };
+    let number = number.parse::<u64>().map_err(|_| invalid())?;
+
+    Ok(Duration::from_secs(number.checked_mul(seconds).ok_or_else(invalid)?))
+}
+
+/// Parses a CPU budget given as a percentage, with or without the `%`.
+pub fn parse_cpu_budget(budget: &str) -> Result<CpuBudget, OptionsError> {
+    budget.trim_end_matches('%')
+        .parse::<f64>()
+        .ok()
+        .and_then(CpuBudget::from_percent)
+        .ok_or_else(|| OptionsError::InvalidCpuBudgetError { budget: budget.to_string() })
+}
+
+/// Parses a hashrate given as hashes per second, optionally with a `k`, `M` or `G` multiplier and
+/// a trailing `H/s`.
+pub fn parse_hashrate(hashrate: &str) -> Result<f64, OptionsError> {
+    let invalid = || OptionsError::InvalidHashrateError { hashrate: hashrate.to_string() };
+    let number = hashrate.trim().trim_end_matches("H/s").trim_end_matches("h/s");
+    let (number, multiplier) = match number.chars().last() {
+        Some('k') | Some('K') => (&number[..number.len() - 1], 1e3),
+        Some('M') => (&number[..number.len() - 1], 1e6),
+        Some('G') => (&number[..number.len() - 1], 1e9),
+        _ => (number, 1.0),
+    };
+
+    match number.trim().parse::<f64>() {
+        Ok(number) if number > 0.0 && number.is_finite() => Ok(number * multiplier),
+        _ => Err(invalid()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parsing_durations() {
+        assert_eq!(parse_duration("90").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_duration("45s").unwrap(), Duration::from_secs(45));
+        assert_eq!(parse_duration("10m").unwrap(), Duration::from_secs(600));
+        assert_eq!(parse_duration("2h").unwrap(), Duration::from_secs(7200));
+        assert!(parse_duration("m").is_err());
+        assert!(parse_duration("10 minutes").is_err());
+        assert!(parse_duration("-5s").is_err());
+    }
+
+    #[test]
+    fn test_parsing_cpu_budgets() {
+        assert_eq!(parse_cpu_budget("50%").unwrap().percent(), 50.0);
+        assert_eq!(parse_cpu_budget("12.5").unwrap().percent(), 12.5);
+        assert!(parse_cpu_budget("0%").is_err());
+        assert!(parse_cpu_budget("200%").is_err());
+        assert!(parse_cpu_budget("half").is_err());
+    }
+
+    #[test]
+    fn test_parsing_hashrates() {
+        assert_eq!(parse_hashrate("1500").unwrap(), 1500.0);
+        assert_eq!(parse_hashrate("250k").unwrap(), 250e3);
+        assert_eq!(parse_hashrate("12.5M").unwrap(), 12.5e6);
+        assert_eq!(parse_hashrate("2 GH/s").unwrap(), 2e9);
+        assert!(parse_hashrate("0").is_err());
+        assert!(parse_hashrate("fast").is_err());
+    }
 }
\ No newline at end of file
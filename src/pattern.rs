@@ -0,0 +1,348 @@
+//! A small language for describing hashes by more than their prefix.
+//!
+//! A pattern is one or more terms separated by commas, and a hash matches when every term does:
+//!
+//! * `prefix:TEMPLATE`, or just `TEMPLATE`, matches hashes starting with the template.
+//! * `suffix:TEMPLATE` matches hashes ending with the template.
+//! * `contains:TEMPLATE` matches hashes with the template anywhere in them.
+//! * `all:digits` and `all:letters` match hashes made only of `0-9` or only of `a-f`.
+//!
+//! Templates are made of lowercase hex digits, which must match exactly, `?` which matches any
+//! digit, `#` which matches `0-9` and `%` which matches `a-f`.  So `00??ff` fixes the first two
+//! and the fifth and sixth digits of the hash, and `suffix:####` asks for four decimal digits at
+//! the end of it.
+
+use crate::errors::PatternError;
+use crate::hasher::DIGEST_LEN;
+use crate::target::{HashPredicate, Prefix};
+use failure::Error;
+
+const HASH_DIGITS: usize = DIGEST_LEN * 2;
+
+#[derive(Clone, Copy)]
+enum Digit {
+    Exact(u8),
+    Any,
+    Decimal,
+    Letter,
+}
+
+impl Digit {
+    fn matches(self, nibble: u8) -> bool {
+        match self {
+            Digit::Exact(expected) => nibble == expected,
+            Digit::Any => true,
+            Digit::Decimal => nibble < 10,
+            Digit::Letter => nibble >= 10,
+        }
+    }
+
+    /// The set of nibbles this digit matches, one bit for each.
+    fn mask(self) -> u16 {
+        match self {
+            Digit::Exact(expected) => 1 << expected,
+            Digit::Any => 0xffff,
+            Digit::Decimal => 0x03ff,
+            Digit::Letter => 0xfc00,
+        }
+    }
+
+    fn probability(self) -> f64 {
+        match self {
+            Digit::Exact(_) => 1.0 / 16.0,
+            Digit::Any => 1.0,
+            Digit::Decimal => 10.0 / 16.0,
+            Digit::Letter => 6.0 / 16.0,
+        }
+    }
+}
+
+enum Term {
+    Prefix(Prefix),
+    At(usize, Vec<Digit>),
+    Contains(Vec<Digit>),
+    All(Digit),
+}
+
+impl Term {
+    fn matches(&self, digest: &[u8; DIGEST_LEN]) -> bool {
+        match self {
+            Term::Prefix(prefix) => prefix.matches(digest),
+            Term::At(offset, template) => matches_at(digest, *offset, template),
+            Term::Contains(template) => (0..=HASH_DIGITS - template.len())
+                .any(|offset| matches_at(digest, offset, template)),
+            Term::All(class) => (0..HASH_DIGITS).all(|i| class.matches(nibble(digest, i))),
+        }
+    }
+
+    fn probability(&self) -> f64 {
+        match self {
+            Term::Prefix(prefix) => prefix.probability(),
+            Term::At(_, template) => template_probability(template),
+            // Treats each position as independent, which is close enough for templates that can't
+            // overlap themselves much.
+            Term::Contains(template) => {
+                let positions = (HASH_DIGITS - template.len() + 1) as i32;
+                1.0 - (1.0 - template_probability(template)).powi(positions)
+            }
+            Term::All(class) => class.probability().powi(HASH_DIGITS as i32),
+        }
+    }
+
+    /// Number of digits matched, counted in from the end of the hash the term is anchored to:
+    /// from the front for prefixes, from the back for suffixes, and from the front of the best
+    /// placed template for `contains`.
+    fn score(&self, digest: &[u8; DIGEST_LEN]) -> usize {
+        match self {
+            Term::Prefix(prefix) => prefix.matching_digits(digest),
+            Term::At(offset, template) if offset + template.len() == HASH_DIGITS && *offset > 0 => {
+                template.iter()
+                    .enumerate()
+                    .rev()
+                    .take_while(|&(i, digit)| digit.matches(nibble(digest, offset + i)))
+                    .count()
+            }
+            Term::At(offset, template) => matching_run(digest, *offset, template),
+            Term::Contains(template) => (0..=HASH_DIGITS - template.len())
+                .map(|offset| matching_run(digest, offset, template))
+                .max()
+                .unwrap_or(0),
+            Term::All(class) => (0..HASH_DIGITS).take_while(|&i| class.matches(nibble(digest, i))).count(),
+        }
+    }
+}
+
+/// A parsed hash pattern.  See the module documentation for the syntax.
+pub struct Pattern {
+    source: String,
+    terms: Vec<Term>,
+}
+
+impl Pattern {
+    /// Parses a pattern, rejecting anything that could never match a hash up front, including
+    /// terms that contradict each other.
+    pub fn parse(pattern: &str) -> Result<Pattern, Error> {
+        let terms = pattern.split(',')
+            .map(|term| parse_term(term, pattern))
+            .collect::<Result<Vec<Term>, Error>>()?;
+        if !satisfiable(&terms) {
+            return Err(PatternError::ContradictionError { pattern: pattern.to_string() }.into());
+        }
+
+        Ok(Pattern { source: pattern.to_string(), terms })
+    }
+
+    /// A pattern with a single prefix term, which may contain wildcards.
+    pub fn prefix(template: &str) -> Result<Pattern, Error> {
+        let term = parse_prefix(template, template)?;
+
+        Ok(Pattern { source: format!("prefix:{}", template), terms: vec![term] })
+    }
+}
+
+impl HashPredicate for Pattern {
+    fn matches(&self, digest: &[u8; DIGEST_LEN]) -> bool {
+        self.terms.iter().all(|term| term.matches(digest))
+    }
+
+    /// Multiplies the chances of each term, as if they were independent.  That is exact for terms
+    /// about different digits and an estimate otherwise.
+    fn probability(&self) -> Option<f64> {
+        Some(self.terms.iter().map(Term::probability).product())
+    }
+
+    fn describe(&self) -> Option<String> {
+        Some(format!("pattern {}", self.source))
+    }
+
+    /// Adds up the digits each term matches, so a hash that satisfies the pattern outscores any
+    /// that doesn't.
+    fn score(&self, digest: &[u8; DIGEST_LEN]) -> u32 {
+        self.terms.iter().map(|term| term.score(digest)).sum::<usize>() as u32
+    }
+}
+
+fn parse_term(term: &str, pattern: &str) -> Result<Term, Error> {
+    let (kind, argument) = match term.find(':') {
+        Some(colon) => (&term[..colon], &term[colon + 1..]),
+        None => ("prefix", term),
+    };
+
+    match kind {
+        "prefix" => parse_prefix(argument, pattern),
+        "suffix" => {
+            let template = parse_template(argument, pattern)?;
+            Ok(Term::At(HASH_DIGITS - template.len(), template))
+        }
+        "contains" => Ok(Term::Contains(parse_template(argument, pattern)?)),
+        "all" => match argument {
+            "digits" => Ok(Term::All(Digit::Decimal)),
+            "letters" => Ok(Term::All(Digit::Letter)),
+            _ => Err(PatternError::UnknownClassError { class: argument.to_string() }.into()),
+        },
+        _ => Err(PatternError::UnknownTermError { term: kind.to_string() }.into()),
+    }
+}
+
+fn parse_prefix(template: &str, pattern: &str) -> Result<Term, Error> {
+    let digits = parse_template(template, pattern)?;
+
+    // Plain hex prefixes get the faster byte-wise comparison.
+    match Prefix::decode(template) {
+        Some(prefix) => Ok(Term::Prefix(prefix)),
+        None => Ok(Term::At(0, digits)),
+    }
+}
+
+fn parse_template(template: &str, pattern: &str) -> Result<Vec<Digit>, Error> {
+    if template.len() > HASH_DIGITS {
+        return Err(PatternError::TooLongError { pattern: pattern.to_string() }.into());
+    }
+
+    template.chars()
+        .map(|c| match c {
+            '0'..='9' | 'a'..='f' => Ok(Digit::Exact(c.to_digit(16).unwrap_or(0) as u8)),
+            '?' => Ok(Digit::Any),
+            '#' => Ok(Digit::Decimal),
+            '%' => Ok(Digit::Letter),
+            'A'..='F' => Err(PatternError::UppercaseError { pattern: pattern.to_string() }.into()),
+            _ => Err(PatternError::InvalidCharacterError { character: c, pattern: pattern.to_string() }.into()),
+        })
+        .collect()
+}
+
+/// Checks that some hash could satisfy every term at once: each digit has to be allowed by every
+/// term that says something about it, and each `contains` template has to fit somewhere around
+/// them.  Two `contains` templates are not checked against each other.
+fn satisfiable(terms: &[Term]) -> bool {
+    let mut allowed = [Digit::Any.mask(); HASH_DIGITS];
+    for term in terms {
+        match term {
+            Term::Prefix(prefix) => {
+                let digits: Vec<Digit> = prefix.to_string().chars()
+                    .map(|c| Digit::Exact(c.to_digit(16).unwrap_or(0) as u8))
+                    .collect();
+                restrict(&mut allowed, 0, &digits);
+            }
+            Term::At(offset, template) => restrict(&mut allowed, *offset, template),
+            Term::All(class) => allowed.iter_mut().for_each(|mask| *mask &= class.mask()),
+            Term::Contains(_) => {}
+        }
+    }
+
+    allowed.iter().all(|&mask| mask != 0) && terms.iter().all(|term| match term {
+        Term::Contains(template) => (0..=HASH_DIGITS - template.len()).any(|offset| {
+            template.iter().enumerate().all(|(i, digit)| allowed[offset + i] & digit.mask() != 0)
+        }),
+        _ => true,
+    })
+}
+
+fn restrict(allowed: &mut [u16; HASH_DIGITS], offset: usize, template: &[Digit]) {
+    for (i, digit) in template.iter().enumerate() {
+        allowed[offset + i] &= digit.mask();
+    }
+}
+
+fn template_probability(template: &[Digit]) -> f64 {
+    template.iter().map(|digit| digit.probability()).product()
+}
+
+fn matches_at(digest: &[u8; DIGEST_LEN], offset: usize, template: &[Digit]) -> bool {
+    template.iter()
+        .enumerate()
+        .all(|(i, digit)| digit.matches(nibble(digest, offset + i)))
+}
+
+fn matching_run(digest: &[u8; DIGEST_LEN], offset: usize, template: &[Digit]) -> usize {
+    template.iter()
+        .enumerate()
+        .take_while(|&(i, digit)| digit.matches(nibble(digest, offset + i)))
+        .count()
+}
+
+fn nibble(digest: &[u8; DIGEST_LEN], index: usize) -> u8 {
+    let byte = digest[index / 2];
+    if index % 2 == 1 { byte & 0xf } else { byte >> 4 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hasher::digest_from_hex;
+
+    #[test]
+    fn test_pattern_terms() -> Result<(), Error> {
+        let digest = digest_from_hex("00c0ffee5bacc70ecd3da2a7880c001c5bd2beef");
+        assert!(Pattern::parse("00c0")?.matches(&digest));
+        assert!(Pattern::parse("prefix:00??ff")?.matches(&digest));
+        assert!(Pattern::parse("prefix:##%")?.matches(&digest));
+        assert!(Pattern::parse("suffix:beef")?.matches(&digest));
+        assert!(Pattern::parse("contains:5bacc7")?.matches(&digest));
+        assert!(Pattern::parse("00,suffix:b??f,contains:c0ffee")?.matches(&digest));
+        assert!(!Pattern::parse("prefix:00?1")?.matches(&digest));
+        assert!(!Pattern::parse("suffix:dead")?.matches(&digest));
+        assert!(!Pattern::parse("contains:c0ffef")?.matches(&digest));
+        assert!(!Pattern::parse("all:digits")?.matches(&digest));
+        Ok(())
+    }
+
+    #[test]
+    fn test_character_classes() -> Result<(), Error> {
+        let digits = digest_from_hex("0123456789012345678901234567890123456789");
+        assert!(Pattern::parse("all:digits")?.matches(&digits));
+        assert!(!Pattern::parse("all:letters")?.matches(&digits));
+        assert!(Pattern::parse("suffix:###")?.matches(&digits));
+        Ok(())
+    }
+
+    #[test]
+    fn test_pattern_probabilities() -> Result<(), Error> {
+        assert_eq!(Pattern::parse("00")?.probability(), Some(1.0 / 256.0));
+        let classes = Pattern::parse("0?#,suffix:%")?.probability().unwrap();
+        assert!((classes - 10.0 * 6.0 / 16f64.powi(3)).abs() < 1e-12);
+        let contains = Pattern::parse("contains:beef")?.probability().unwrap();
+        assert!((contains - 37.0 / 65536.0).abs() < 1e-6);
+        Ok(())
+    }
+
+    #[test]
+    fn test_pattern_scores() -> Result<(), Error> {
+        let digest = digest_from_hex("00c0ffee5bacc70ecd3da2a7880c001c5bd2beef");
+        assert_eq!(Pattern::parse("0000")?.score(&digest), 2);
+        assert_eq!(Pattern::parse("prefix:00?1")?.score(&digest), 3);
+        assert_eq!(Pattern::parse("suffix:dbef")?.score(&digest), 2);
+        assert_eq!(Pattern::parse("contains:c0ffef")?.score(&digest), 5);
+        assert_eq!(Pattern::parse("all:digits")?.score(&digest), 2);
+        assert_eq!(Pattern::parse("00,suffix:beef")?.score(&digest), 6);
+        Ok(())
+    }
+
+    #[test]
+    fn test_pattern_descriptions() -> Result<(), Error> {
+        assert_eq!(Pattern::parse("00,suffix:##")?.describe(), Some("pattern 00,suffix:##".to_string()));
+        assert_eq!(Pattern::prefix("00?")?.describe(), Some("pattern prefix:00?".to_string()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_patterns_are_rejected() {
+        assert!(Pattern::parse("00C0").is_err());
+        assert!(Pattern::parse("00x0").is_err());
+        assert!(Pattern::parse("middle:00").is_err());
+        assert!(Pattern::parse("all:vowels").is_err());
+        assert!(Pattern::parse(&format!("suffix:{}", "0".repeat(41))).is_err());
+        assert!(Pattern::prefix("00?").is_ok());
+    }
+
+    #[test]
+    fn test_contradictory_patterns_are_rejected() {
+        assert!(Pattern::parse("all:digits,all:letters").is_err());
+        assert!(Pattern::parse("prefix:0,prefix:1").is_err());
+        assert!(Pattern::parse("00,prefix:?1").is_err());
+        assert!(Pattern::parse("a,all:digits").is_err());
+        assert!(Pattern::parse("contains:ab,all:digits").is_err());
+        assert!(Pattern::parse(&format!("prefix:{},suffix:1", "0".repeat(40))).is_err());
+        assert!(Pattern::parse("00,prefix:?0?#,all:digits,contains:99").is_ok());
+    }
+}
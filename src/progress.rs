@@ -0,0 +1,295 @@
+use crate::estimate::Estimate;
+use crate::hasher::DIGEST_LEN;
+use std::io::{self, IsTerminal, Write};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How often a miner reports its progress to its observer, unless told otherwise.
+pub const REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A snapshot of how a search is going.
+#[derive(Clone, Debug)]
+pub struct Progress {
+    /// Hashes tried so far by each worker thread.
+    pub thread_attempts: Vec<u64>,
+    /// Time since the search started.
+    pub elapsed: Duration,
+    /// The lowest hash found so far, which is the one with the most leading zeros.
+    pub best_hash: Option<String>,
+    /// The chance that any one attempt succeeds, if the predicate being mined for knows it.
+    pub probability: Option<f64>,
+    /// How long a search against the clock has in all, after which it commits the best hash it
+    /// found.
+    pub budget: Option<Duration>,
+}
+
+impl Progress {
+    /// Hashes tried so far by all threads.
+    pub fn attempts(&self) -> u64 {
+        self.thread_attempts.iter().sum()
+    }
+
+    /// Hashes per second over all threads.
+    pub fn hashrate(&self) -> f64 {
+        rate(self.attempts(), self.elapsed)
+    }
+
+    /// Hashes per second of each worker thread.
+    pub fn thread_hashrates(&self) -> Vec<f64> {
+        self.thread_attempts.iter().map(|&attempts| rate(attempts, self.elapsed)).collect()
+    }
+
+    /// Number of attempts a search like this takes on average.
+    pub fn expected_attempts(&self) -> Option<f64> {
+        self.estimate().map(|estimate| estimate.expected_attempts())
+    }
+
+    /// Expected time until the next match at the current hashrate.  Every attempt is equally
+    /// likely to succeed, so the attempts already made don't bring it any closer, and a search
+    /// that has run for a long time still expects the same wait as one that has just started.
+    /// A wait too long for a `Duration` saturates at `Duration::MAX`.  A search against the
+    /// clock isn't waiting for a match, so its ETA is the time it has left.
+    pub fn eta(&self) -> Option<Duration> {
+        if let Some(budget) = self.budget {
+            return Some(budget.saturating_sub(self.elapsed));
+        }
+        self.estimate()?.eta(self.hashrate()).map(|eta| eta.expected)
+    }
+
+    fn estimate(&self) -> Option<Estimate> {
+        self.probability.and_then(Estimate::new)
+    }
+}
+
+/// Receives periodic updates from a miner while it searches.  Observers are called from a
+/// separate reporting thread, so they can take their time without slowing the workers down.
+pub trait MiningObserver: Send + Sync {
+    /// Called every report interval while the search runs.
+    fn on_progress(&self, progress: &Progress);
+
+    /// Called once when the search stops, whether or not it found anything.
+    fn on_finish(&self, _progress: &Progress) {}
+}
+
+/// The counters a miner's workers update as they go, from which `Progress` snapshots are taken.
+pub(crate) struct Stats {
+    started: Instant,
+    budget: Option<Duration>,
+    thread_attempts: Vec<AtomicU64>,
+    best: Mutex<Option<[u8; DIGEST_LEN]>>,
+}
+
+impl Stats {
+    pub(crate) fn new(threads: usize) -> Stats {
+        Stats {
+            started: Instant::now(),
+            budget: None,
+            thread_attempts: (0..threads.max(1)).map(|_| AtomicU64::new(0)).collect(),
+            best: Mutex::new(None),
+        }
+    }
+
+    /// Counts towards a search against the clock that has `budget` in all.
+    pub(crate) fn with_budget(mut self, budget: Option<Duration>) -> Stats {
+        self.budget = budget;
+        self
+    }
+
+    /// Records a batch of attempts made by the current worker thread, and the lowest digest among
+    /// them.
+    pub(crate) fn record(&self, attempts: u64, best: &[u8; DIGEST_LEN]) {
+        let thread = rayon::current_thread_index().unwrap_or(0) % self.thread_attempts.len();
+        self.thread_attempts[thread].fetch_add(attempts, Ordering::Relaxed);
+
+        if let Ok(mut current) = self.best.lock() {
+            if current.is_none_or(|current| best < &current) {
+                *current = Some(*best);
+            }
+        }
+    }
+
+    pub(crate) fn snapshot(&self, probability: Option<f64>) -> Progress {
+        let best = self.best.lock().ok().and_then(|best| *best);
+
+        Progress {
+            thread_attempts: self.thread_attempts.iter().map(|attempts| attempts.load(Ordering::Relaxed)).collect(),
+            elapsed: self.started.elapsed(),
+            best_hash: best.map(|digest| digest.iter().map(|byte| format!("{:02x}", byte)).collect()),
+            probability,
+            budget: self.budget,
+        }
+    }
+}
+
+/// The observer used by the command line.  It keeps a one line summary with an ETA up to date on
+/// stderr when that is a terminal, and dumps the full stats to stderr whenever the process
+/// receives SIGUSR1.
+pub struct ProgressLine {
+    terminal: bool,
+    dump_requested: Arc<AtomicBool>,
+}
+
+impl ProgressLine {
+    /// Creates the observer and starts listening for SIGUSR1.
+    pub fn new() -> ProgressLine {
+        let dump_requested = Arc::new(AtomicBool::new(false));
+        #[cfg(unix)]
+        let _ = signal_hook::flag::register(signal_hook::consts::SIGUSR1, dump_requested.clone());
+
+        ProgressLine {
+            terminal: io::stderr().is_terminal(),
+            dump_requested,
+        }
+    }
+
+    fn dump(&self, progress: &Progress) {
+        let mut stderr = io::stderr().lock();
+        if self.terminal {
+            let _ = write!(stderr, "\r\x1b[K");
+        }
+
+        let _ = writeln!(stderr, "gitchain: {} attempts in {}", progress.attempts(), format_duration(progress.elapsed));
+        let _ = writeln!(stderr, "  total hashrate: {}", format_hashrate(progress.hashrate()));
+        for (thread, hashrate) in progress.thread_hashrates().iter().enumerate() {
+            let _ = writeln!(stderr, "  thread {}: {}", thread, format_hashrate(*hashrate));
+        }
+        if let Some(expected) = progress.expected_attempts() {
+            let _ = writeln!(stderr, "  expected attempts: {:.0}", expected);
+        }
+        if let Some(best) = &progress.best_hash {
+            let _ = writeln!(stderr, "  best hash so far: {}", best);
+        }
+    }
+}
+
+impl Default for ProgressLine {
+    fn default() -> ProgressLine {
+        ProgressLine::new()
+    }
+}
+
+impl MiningObserver for ProgressLine {
+    fn on_progress(&self, progress: &Progress) {
+        if self.dump_requested.swap(false, Ordering::Relaxed) {
+            self.dump(progress);
+        }
+        if !self.terminal {
+            return;
+        }
+
+        let threads = progress.thread_attempts.len();
+        let eta = progress.eta().map(format_duration).unwrap_or_else(|| "unknown".to_string());
+        let best = progress.best_hash.as_ref().map(|hash| &hash[..12]).unwrap_or("none");
+        eprint!(
+            "\r\x1b[K{} attempts, {} ({} per thread), {} elapsed, ETA {}, best {}",
+            progress.attempts(),
+            format_hashrate(progress.hashrate()),
+            format_hashrate(progress.hashrate() / threads as f64),
+            format_duration(progress.elapsed),
+            eta,
+            best,
+        );
+    }
+
+    fn on_finish(&self, _progress: &Progress) {
+        if self.terminal {
+            eprint!("\r\x1b[K");
+        }
+    }
+}
+
+/// Formats a hashrate with the largest unit that keeps it above one, such as `13.20 MH/s`.
+pub fn format_hashrate(hashrate: f64) -> String {
+    let units = ["H/s", "kH/s", "MH/s", "GH/s"];
+    let mut value = hashrate;
+    let mut unit = 0;
+    while value >= 1000.0 && unit < units.len() - 1 {
+        value /= 1000.0;
+        unit += 1;
+    }
+
+    format!("{:.2} {}", value, units[unit])
+}
+
+/// Formats a duration in whole seconds, minutes, hours and days, such as `1h 02m 03s`.
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    let (days, hours, minutes, seconds) = (seconds / 86400, seconds / 3600 % 24, seconds / 60 % 60, seconds % 60);
+
+    if days > 0 {
+        format!("{}d {:02}h {:02}m", days, hours, minutes)
+    } else if hours > 0 {
+        format!("{}h {:02}m {:02}s", hours, minutes, seconds)
+    } else if minutes > 0 {
+        format!("{}m {:02}s", minutes, seconds)
+    } else {
+        format!("{}s", seconds)
+    }
+}
+
+fn rate(attempts: u64, elapsed: Duration) -> f64 {
+    let seconds = elapsed.as_secs_f64();
+    if seconds > 0.0 { attempts as f64 / seconds } else { 0.0 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_progress_rates_and_eta() {
+        let progress = Progress {
+            thread_attempts: vec![3000, 1000],
+            elapsed: Duration::from_secs(2),
+            best_hash: None,
+            probability: Some(1.0 / 16000.0),
+            budget: None,
+        };
+
+        assert_eq!(progress.attempts(), 4000);
+        assert_eq!(progress.hashrate(), 2000.0);
+        assert_eq!(progress.thread_hashrates(), vec![1500.0, 500.0]);
+        assert_eq!(progress.eta(), Some(Duration::from_secs(8)));
+
+        let overdue = Progress { thread_attempts: vec![20000, 12000], elapsed: Duration::from_secs(16), ..progress };
+        assert_eq!(overdue.eta(), Some(Duration::from_secs(8)));
+    }
+
+    #[test]
+    fn test_eta_of_a_hopeless_search_saturates() {
+        let progress = Progress {
+            thread_attempts: vec![1],
+            elapsed: Duration::from_secs(1),
+            best_hash: None,
+            probability: Some(0.5f64.powi(160)),
+            budget: None,
+        };
+
+        assert_eq!(progress.eta(), Some(Duration::MAX));
+
+        let timed = Progress { budget: Some(Duration::from_secs(3)), ..progress };
+        assert_eq!(timed.eta(), Some(Duration::from_secs(2)));
+    }
+
+    #[test]
+    fn test_stats_keep_the_lowest_digest() {
+        let stats = Stats::new(1);
+        stats.record(10, &[0x0f; DIGEST_LEN]);
+        stats.record(10, &[0xf0; DIGEST_LEN]);
+
+        let progress = stats.snapshot(None);
+        assert_eq!(progress.attempts(), 20);
+        assert_eq!(progress.best_hash, Some("0f".repeat(DIGEST_LEN)));
+        assert_eq!(progress.eta(), None);
+    }
+
+    #[test]
+    fn test_formatting() {
+        assert_eq!(format_hashrate(13_200_000.0), "13.20 MH/s");
+        assert_eq!(format_hashrate(12.0), "12.00 H/s");
+        assert_eq!(format_duration(Duration::from_secs(42)), "42s");
+        assert_eq!(format_duration(Duration::from_secs(3723)), "1h 02m 03s");
+        assert_eq!(format_duration(Duration::from_secs(90061)), "1d 01h 01m");
+    }
+}
@@ -0,0 +1,522 @@
+use crate::errors::TargetError;
+use crate::hasher::DIGEST_LEN;
+use failure::Error;
+use std::fmt;
+use std::sync::Arc;
+
+/// Default prefix mined for when no target is given.
+pub const DEFAULT_PREFIX: &str = "000000";
+
+/// A condition on the raw digest of a commit, which the miner searches for a nonce to satisfy.
+/// Implement this to mine for anything that can be decided from the 20 digest bytes.
+pub trait HashPredicate: Send + Sync {
+    /// Checks whether a raw digest satisfies this predicate.
+    fn matches(&self, digest: &[u8; DIGEST_LEN]) -> bool;
+
+    /// The chance that a random digest satisfies this predicate, if it can be worked out.  This is
+    /// what estimates of how long mining will take are based on.  A Committer refuses to mine for
+    /// a predicate without one, or with a chance of zero.
+    fn probability(&self) -> Option<f64> {
+        None
+    }
+
+    /// A description of what this predicate matches, which tells apart checkpoints of searches for
+    /// different things.  Searches for predicates without one are never checkpointed.
+    fn describe(&self) -> Option<String> {
+        None
+    }
+
+    /// How close a digest comes to satisfying this predicate, higher being closer, and highest for
+    /// digests that satisfy it.  Mining for a limited time keeps the best scoring hash it finds.
+    /// Defaults to the number of zero bits the digest starts with.
+    fn score(&self, digest: &[u8; DIGEST_LEN]) -> u32 {
+        leading_zero_bits(digest)
+    }
+}
+
+impl<P: HashPredicate + ?Sized> HashPredicate for Box<P> {
+    fn matches(&self, digest: &[u8; DIGEST_LEN]) -> bool {
+        (**self).matches(digest)
+    }
+
+    fn probability(&self) -> Option<f64> {
+        (**self).probability()
+    }
+
+    fn describe(&self) -> Option<String> {
+        (**self).describe()
+    }
+    fn score(&self, digest: &[u8; DIGEST_LEN]) -> u32 {
+        (**self).score(digest)
+    }
+}
+
+impl<P: HashPredicate + ?Sized> HashPredicate for Arc<P> {
+    fn matches(&self, digest: &[u8; DIGEST_LEN]) -> bool {
+        (**self).matches(digest)
+    }
+
+    fn probability(&self) -> Option<f64> {
+        (**self).probability()
+    }
+
+    fn describe(&self) -> Option<String> {
+        (**self).describe()
+    }
+    fn score(&self, digest: &[u8; DIGEST_LEN]) -> u32 {
+        (**self).score(digest)
+    }
+}
+
+/// What a mined commit hash has to satisfy.
+pub enum Target {
+    /// The hash starts with a hex prefix.
+    Prefix(Prefix),
+    /// The hash starts with at least this many zero bits.
+    LeadingZeroBits(u32),
+    /// The hash, read as a big-endian 160 bit number, is less than this threshold.
+    Threshold([u8; DIGEST_LEN]),
+}
+
+impl Target {
+    /// Targets hashes starting with `prefix`, which must be lowercase hex and no longer than a
+    /// hash.
+    pub fn prefix(prefix: &str) -> Result<Target, Error> {
+        let prefix = Prefix::decode(prefix)
+            .ok_or(TargetError::InvalidPrefixError { prefix: prefix.to_string() })?;
+
+        Ok(Target::Prefix(prefix))
+    }
+
+    /// Targets hashes starting with at least `bits` zero bits.  Each extra bit doubles the
+    /// expected work, compared to sixteen times for each extra hex digit of a prefix.
+    pub fn leading_zero_bits(bits: u32) -> Result<Target, Error> {
+        if bits as usize > DIGEST_LEN * 8 {
+            return Err(TargetError::TooManyBitsError { bits }.into());
+        }
+
+        Ok(Target::LeadingZeroBits(bits))
+    }
+
+    /// Targets hashes numerically less than `threshold`, given as 40 lowercase hex digits like a
+    /// Bitcoin style target.  A threshold of zero is rejected, as no hash is less than it.
+    pub fn threshold(threshold: &str) -> Result<Target, Error> {
+        let invalid = || TargetError::InvalidThresholdError { threshold: threshold.to_string() };
+        let lowercase_hex = |byte: &u8| byte.is_ascii_digit() || (b'a'..=b'f').contains(byte);
+        if threshold.len() != DIGEST_LEN * 2 || !threshold.as_bytes().iter().all(lowercase_hex) {
+            return Err(invalid().into());
+        }
+
+        let mut bytes = [0u8; DIGEST_LEN];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&threshold[i * 2..i * 2 + 2], 16).map_err(|_| invalid())?;
+        }
+        if bytes.iter().all(|&byte| byte == 0) {
+            return Err(TargetError::ZeroThresholdError {}.into());
+        }
+
+        Ok(Target::Threshold(bytes))
+    }
+}
+
+impl HashPredicate for Target {
+    fn matches(&self, digest: &[u8; DIGEST_LEN]) -> bool {
+        match self {
+            Target::Prefix(prefix) => prefix.matches(digest),
+            Target::LeadingZeroBits(bits) => leading_zero_bits(digest) >= *bits,
+            Target::Threshold(threshold) => digest < threshold,
+        }
+    }
+
+    fn probability(&self) -> Option<f64> {
+        let probability = match self {
+            Target::Prefix(prefix) => prefix.probability(),
+            Target::LeadingZeroBits(bits) => 0.5f64.powi(*bits as i32),
+            Target::Threshold(threshold) => threshold.iter()
+                .enumerate()
+                .map(|(i, &byte)| f64::from(byte) * 256f64.powi(-(i as i32 + 1)))
+                .sum(),
+        };
+
+        Some(probability)
+    }
+
+    fn describe(&self) -> Option<String> {
+        let description = match self {
+            Target::Prefix(prefix) => format!("prefix {}", prefix),
+            Target::LeadingZeroBits(bits) => format!("bits {}", bits),
+            Target::Threshold(threshold) => format!("target {}", hex(threshold)),
+        };
+
+        Some(description)
+    }
+
+    /// Scores prefixes by how many of their digits the hash starts with, and leading zero bits by
+    /// the zero bits the hash starts with.  A hash at or above a threshold scores the number of
+    /// leading bits it shares with the threshold, which grows the closer it comes from above, and
+    /// any hash below the threshold outscores them all.
+    fn score(&self, digest: &[u8; DIGEST_LEN]) -> u32 {
+        match self {
+            Target::Prefix(prefix) => prefix.matching_digits(digest) as u32,
+            Target::LeadingZeroBits(_) => leading_zero_bits(digest),
+            Target::Threshold(threshold) if digest < threshold => (DIGEST_LEN * 8) as u32 + 1,
+            Target::Threshold(threshold) => common_leading_bits(digest, threshold),
+        }
+    }
+}
+
+/// Checks that a predicate has a known, nonzero chance of matching, so mining for it will end.
+/// Anything else, such as a typo that can never match, would search every nonce there is.
+pub fn ensure_satisfiable(predicate: &dyn HashPredicate) -> Result<(), Error> {
+    match predicate.probability() {
+        Some(probability) if probability > 0.0 => Ok(()),
+        _ => Err(TargetError::UnsatisfiableError {}.into()),
+    }
+}
+
+/// Counts the zero bits a raw digest starts with.
+pub fn leading_zero_bits(digest: &[u8; DIGEST_LEN]) -> u32 {
+    let mut bits = 0;
+    for byte in digest.iter() {
+        bits += byte.leading_zeros();
+        if *byte != 0 {
+            break;
+        }
+    }
+    bits
+}
+
+/// Counts the bits two digests share before they first differ.
+fn common_leading_bits(a: &[u8; DIGEST_LEN], b: &[u8; DIGEST_LEN]) -> u32 {
+    let mut bits = 0;
+    for (x, y) in a.iter().zip(b.iter()) {
+        bits += (x ^ y).leading_zeros();
+        if x != y {
+            break;
+        }
+    }
+    bits
+}
+
+/// A hex prefix decoded into raw digest bytes, so candidates can be checked against it without
+/// formatting their hash as a string.
+pub struct Prefix {
+    bytes: Vec<u8>,
+    half: Option<u8>,
+}
+
+impl Prefix {
+    /// Decodes a prefix of lowercase hex digits.  Returns `None` if the prefix could never match a
+    /// hash, because it contains other characters or is longer than a hash.
+    pub fn decode(prefix: &str) -> Option<Prefix> {
+        if prefix.len() > DIGEST_LEN * 2 {
+            return None;
+        }
+
+        let mut nibbles = Vec::with_capacity(prefix.len());
+        for c in prefix.chars() {
+            match c {
+                '0'..='9' | 'a'..='f' => nibbles.push(c.to_digit(16)? as u8),
+                _ => return None,
+            }
+        }
+
+        let bytes = nibbles.chunks(2)
+            .filter(|pair| pair.len() == 2)
+            .map(|pair| pair[0] << 4 | pair[1])
+            .collect();
+        let half = if nibbles.len() % 2 == 1 { nibbles.last().map(|n| n << 4) } else { None };
+
+        Some(Prefix { bytes, half })
+    }
+
+    /// Checks whether a raw digest starts with this prefix.
+    pub fn matches(&self, digest: &[u8; DIGEST_LEN]) -> bool {
+        let full = self.bytes.len();
+        if digest[..full] != self.bytes[..] {
+            return false;
+        }
+
+        match self.half {
+            Some(half) => digest[full] & 0xf0 == half,
+            None => true,
+        }
+    }
+
+    /// Number of digits of this prefix that a raw digest starts with.
+    pub fn matching_digits(&self, digest: &[u8; DIGEST_LEN]) -> usize {
+        let full = self.bytes.iter().zip(digest.iter()).take_while(|(a, b)| a == b).count();
+        if full < self.bytes.len() {
+            let high = self.bytes[full] & 0xf0 == digest[full] & 0xf0;
+            return full * 2 + high as usize;
+        }
+
+        match self.half {
+            Some(half) => full * 2 + (digest[full] & 0xf0 == half) as usize,
+            None => full * 2,
+        }
+    }
+
+    /// Number of hex digits in this prefix.
+    pub fn digits(&self) -> usize {
+        self.bytes.len() * 2 + self.half.map_or(0, |_| 1)
+    }
+
+    /// The chance that a random digest starts with this prefix.
+    pub fn probability(&self) -> f64 {
+        16f64.powi(-(self.digits() as i32))
+    }
+}
+
+impl fmt::Display for Prefix {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", hex(&self.bytes))?;
+        match self.half {
+            Some(half) => write!(f, "{:x}", half >> 4),
+            None => Ok(()),
+        }
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Number of buckets in a `PrefixSet`'s table, one for every value of a hash's first two bytes.
+const BUCKETS: usize = 1 << 16;
+
+/// A set of acceptable prefixes, such as a team's vanity prefixes or a dictionary of hexspeak
+/// words, any one of which a hash may start with.  Prefixes are filed into a table indexed by the
+/// first two bytes of the hashes they can match, so checking a hash only compares it against the
+/// handful sharing its bucket, and thousands of prefixes cost about the same as one.
+pub struct PrefixSet {
+    words: Vec<String>,
+    prefixes: Vec<Prefix>,
+    table: Vec<Vec<u32>>,
+}
+
+impl PrefixSet {
+    /// Builds a set from lowercase hex prefixes.  When a hash matches several of them, the one
+    /// given first is reported.  An empty set is rejected, as no hash could match it.
+    pub fn new<S: AsRef<str>>(words: &[S]) -> Result<PrefixSet, Error> {
+        if words.is_empty() {
+            return Err(TargetError::EmptyPrefixSetError {}.into());
+        }
+
+        let mut table = vec![Vec::new(); BUCKETS];
+        let mut prefixes = Vec::with_capacity(words.len());
+
+        for (index, word) in words.iter().enumerate() {
+            let word = word.as_ref();
+            let prefix = Prefix::decode(word)
+                .ok_or(TargetError::InvalidPrefixError { prefix: word.to_string() })?;
+
+            // A prefix shorter than the four digits of the key falls into every bucket whose key
+            // starts with it.
+            let known = word.len().min(4);
+            let key = u32::from_str_radix(&format!("{:0<4}", &word[..known]), 16).unwrap_or(0) as usize;
+            let spread = 1 << (4 * (4 - known));
+            for bucket in &mut table[key..key + spread] {
+                bucket.push(index as u32);
+            }
+
+            prefixes.push(prefix);
+        }
+
+        Ok(PrefixSet {
+            words: words.iter().map(|word| word.as_ref().to_string()).collect(),
+            prefixes,
+            table,
+        })
+    }
+
+    /// Reads a set from text with one prefix per line, ignoring blank lines and anything after a
+    /// `#`.
+    pub fn parse(text: &str) -> Result<PrefixSet, Error> {
+        let words: Vec<&str> = text.lines()
+            .map(|line| line.split('#').next().unwrap_or("").trim())
+            .filter(|line| !line.is_empty())
+            .collect();
+
+        PrefixSet::new(&words)
+    }
+
+    /// Returns the prefix a raw digest starts with, if any.
+    pub fn matching(&self, digest: &[u8; DIGEST_LEN]) -> Option<&str> {
+        let key = (digest[0] as usize) << 8 | digest[1] as usize;
+        self.table[key].iter()
+            .find(|&&index| self.prefixes[index as usize].matches(digest))
+            .map(|&index| self.words[index as usize].as_str())
+    }
+
+    /// Returns the prefix a hex hash starts with, if any.
+    pub fn matching_hash(&self, hash: &str) -> Option<&str> {
+        self.words.iter()
+            .find(|word| hash.starts_with(word.as_str()))
+            .map(String::as_str)
+    }
+
+    /// Number of prefixes in the set.
+    pub fn len(&self) -> usize {
+        self.words.len()
+    }
+
+    /// Whether the set has no prefixes, which `new` never allows.
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+}
+
+impl HashPredicate for PrefixSet {
+    fn matches(&self, digest: &[u8; DIGEST_LEN]) -> bool {
+        self.matching(digest).is_some()
+    }
+
+    /// Adds up the chances of each prefix, which slightly overestimates the chance of a match when
+    /// one prefix starts with another.
+    fn probability(&self) -> Option<f64> {
+        let total: f64 = self.prefixes.iter().map(Prefix::probability).sum();
+        Some(total.min(1.0))
+    }
+
+    fn describe(&self) -> Option<String> {
+        Some(format!("prefixes {}", self.words.join(",")))
+    }
+
+    /// Scores a hash by the most digits of any one prefix it starts with.  This compares it against
+    /// every prefix, so it is much slower than `matches` for large sets.
+    fn score(&self, digest: &[u8; DIGEST_LEN]) -> u32 {
+        self.prefixes.iter().map(|prefix| prefix.matching_digits(digest)).max().unwrap_or(0) as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hasher::digest_from_hex;
+
+    #[test]
+    fn test_prefix_matches_odd_and_even_lengths() {
+        let digest = digest_from_hex("00c0ffee5bacc70ecd3da2a7880c001c5bd2ff4a");
+        assert!(Prefix::decode("").unwrap().matches(&digest));
+        assert!(Prefix::decode("00c").unwrap().matches(&digest));
+        assert!(Prefix::decode("00c0ffee").unwrap().matches(&digest));
+        assert!(!Prefix::decode("00c1").unwrap().matches(&digest));
+        assert!(!Prefix::decode("01").unwrap().matches(&digest));
+    }
+
+    #[test]
+    fn test_leading_zero_bits_target() -> Result<(), Error> {
+        let digest = digest_from_hex("0003ffee5bacc70ecd3da2a7880c001c5bd2ff4a");
+        assert_eq!(leading_zero_bits(&digest), 14);
+        assert!(Target::leading_zero_bits(14)?.matches(&digest));
+        assert!(!Target::leading_zero_bits(15)?.matches(&digest));
+        assert!(Target::leading_zero_bits(161).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_scores() -> Result<(), Error> {
+        let digest = digest_from_hex("00c0ffee5bacc70ecd3da2a7880c001c5bd2ff4a");
+        assert_eq!(Target::prefix("00c1")?.score(&digest), 3);
+        assert_eq!(Target::prefix("00c0f")?.score(&digest), 5);
+        assert_eq!(Target::prefix("1")?.score(&digest), 0);
+        assert_eq!(Target::leading_zero_bits(40)?.score(&digest), 8);
+        assert_eq!(PrefixSet::new(&["0c", "00c0ff", "00d"])?.score(&digest), 6);
+
+        let threshold = Target::threshold("00c0000000000000000000000000000000000000")?;
+        let below = digest_from_hex("00bfffffffffffffffffffffffffffffffffffff");
+        let just_above = digest_from_hex("00c0000000000000000000000000000000000001");
+        let far_above = digest_from_hex("0100000000000000000000000000000000000000");
+        assert!(threshold.score(&below) > threshold.score(&just_above));
+        assert!(threshold.score(&just_above) > threshold.score(&digest));
+        assert!(threshold.score(&digest) > threshold.score(&far_above));
+        Ok(())
+    }
+
+    #[test]
+    fn test_threshold_target() -> Result<(), Error> {
+        let digest = digest_from_hex("0003ffee5bacc70ecd3da2a7880c001c5bd2ff4a");
+        assert!(Target::threshold("0004000000000000000000000000000000000000")?.matches(&digest));
+        assert!(!Target::threshold("0003ffee5bacc70ecd3da2a7880c001c5bd2ff4a")?.matches(&digest));
+        assert!(Target::threshold("0004").is_err());
+        assert!(Target::threshold("000400000000000000000000000000000000000g").is_err());
+        assert!(Target::threshold("+00400000000000000000000000000000000000f").is_err());
+        assert!(Target::threshold("000400000000000000000000000000000000000F").is_err());
+        assert!(Target::threshold(&format!("{}é", "0".repeat(38))).is_err());
+        assert!(Target::threshold(&"0".repeat(40)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_prefix_set_reports_the_matching_prefix() -> Result<(), Error> {
+        let set = PrefixSet::parse("cafe\nbeef # hexspeak\n\nc0ffee\n00c0\n00\n")?;
+        assert_eq!(set.len(), 5);
+
+        let digest = digest_from_hex("00c0ffee5bacc70ecd3da2a7880c001c5bd2ff4a");
+        assert_eq!(set.matching(&digest), Some("00c0"));
+        assert_eq!(set.matching_hash("00c0ffee5bacc70ecd3da2a7880c001c5bd2ff4a"), Some("00c0"));
+
+        let digest = digest_from_hex("c0ffee0e5bacc70ecd3da2a7880c001c5bd2ff4a");
+        assert_eq!(set.matching(&digest), Some("c0ffee"));
+
+        let digest = digest_from_hex("cafa0e5bacc70ecd3da2a7880c001c5bd2ff4a00");
+        assert_eq!(set.matching(&digest), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_prefix_set_short_prefixes_cover_every_bucket() -> Result<(), Error> {
+        let set = PrefixSet::new(&["c"])?;
+        assert!(set.matches(&digest_from_hex("c000000000000000000000000000000000000000")));
+        assert!(set.matches(&digest_from_hex("cfff000000000000000000000000000000000000")));
+        assert!(!set.matches(&digest_from_hex("d000000000000000000000000000000000000000")));
+        assert!(PrefixSet::new(&["cafe", "BEEF"]).is_err());
+        assert!(PrefixSet::new::<&str>(&[]).is_err());
+        assert!(PrefixSet::parse("# only a comment\n\n").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_target_probabilities() -> Result<(), Error> {
+        assert_eq!(Target::prefix("000")?.probability(), Some(1.0 / 4096.0));
+        assert_eq!(Target::leading_zero_bits(12)?.probability(), Some(1.0 / 4096.0));
+        let threshold = Target::threshold("0010000000000000000000000000000000000000")?;
+        assert_eq!(threshold.probability(), Some(1.0 / 4096.0));
+        let set = PrefixSet::new(&["00", "ff"])?;
+        assert_eq!(set.probability(), Some(2.0 / 256.0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_unsatisfiable_predicates_are_rejected() -> Result<(), Error> {
+        struct Never;
+        impl HashPredicate for Never {
+            fn matches(&self, _digest: &[u8; DIGEST_LEN]) -> bool {
+                false
+            }
+        }
+
+        assert!(ensure_satisfiable(&Never).is_err());
+        assert!(ensure_satisfiable(&Target::leading_zero_bits(160)?).is_ok());
+        assert!(ensure_satisfiable(&Target::threshold(&format!("{}1", "0".repeat(39)))?).is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn test_target_descriptions() -> Result<(), Error> {
+        assert_eq!(Target::prefix("c0ffe")?.describe(), Some("prefix c0ffe".to_string()));
+        assert_eq!(Target::leading_zero_bits(26)?.describe(), Some("bits 26".to_string()));
+        let threshold = "0000003fffffffffffffffffffffffffffffffff";
+        assert_eq!(Target::threshold(threshold)?.describe(), Some(format!("target {}", threshold)));
+        assert_eq!(PrefixSet::new(&["cafe", "beef"])?.describe(), Some("prefixes cafe,beef".to_string()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_prefix_rejects_impossible_prefixes() {
+        assert!(Prefix::decode("00C0").is_none());
+        assert!(Prefix::decode("00g0").is_none());
+        assert!(Prefix::decode(&"0".repeat(41)).is_none());
+    }
+}
@@ -0,0 +1,86 @@
+use std::cell::Cell;
+use std::time::Duration;
+
+/// The share of each worker's time it may spend mining.  After every chunk of work, a worker
+/// sleeps long enough that its average CPU use stays near the budget.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CpuBudget {
+    fraction: f64,
+}
+
+impl CpuBudget {
+    /// A budget of `percent` percent of each worker's time.  Returns `None` unless it is more than
+    /// 0 and at most 100.
+    pub fn from_percent(percent: f64) -> Option<CpuBudget> {
+        if percent > 0.0 && percent <= 100.0 {
+            Some(CpuBudget { fraction: percent / 100.0 })
+        } else {
+            None
+        }
+    }
+
+    /// The budget as a percentage.
+    pub fn percent(&self) -> f64 {
+        self.fraction * 100.0
+    }
+
+    /// How long to sleep after `busy` spent mining, to bring the average down to the budget.
+    pub fn pause_after(&self, busy: Duration) -> Duration {
+        busy.mul_f64((1.0 - self.fraction) / self.fraction)
+    }
+}
+
+thread_local! {
+    static LOWERED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Lowers the scheduling priority of the calling thread, the first time it is called on that
+/// thread.  On Linux the thread moves to `SCHED_IDLE`, so it only runs when nothing else wants
+/// the CPU, or failing that to the lowest nice level, which Linux keeps per thread.  Other
+/// systems only have a nice level for the whole process, so the priority is left alone there
+/// rather than slow down every other thread too.  Priority can't be raised back without
+/// privileges, so this lasts as long as the thread does.
+pub fn lower_priority() {
+    if LOWERED.with(|lowered| lowered.replace(true)) {
+        return;
+    }
+
+    #[cfg(target_os = "linux")]
+    unsafe {
+        let param = libc::sched_param { sched_priority: 0 };
+        if libc::sched_setscheduler(0, libc::SCHED_IDLE, &param) != 0 {
+            let thread = libc::gettid() as libc::id_t;
+            libc::setpriority(libc::PRIO_PROCESS, thread, 19);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cpu_budget_pauses() {
+        let half = CpuBudget::from_percent(50.0).unwrap();
+        assert_eq!(half.pause_after(Duration::from_millis(10)), Duration::from_millis(10));
+
+        let quarter = CpuBudget::from_percent(25.0).unwrap();
+        assert_eq!(quarter.pause_after(Duration::from_millis(10)), Duration::from_millis(30));
+
+        let all = CpuBudget::from_percent(100.0).unwrap();
+        assert_eq!(all.pause_after(Duration::from_millis(10)), Duration::from_millis(0));
+
+        assert!(CpuBudget::from_percent(0.0).is_none());
+        assert!(CpuBudget::from_percent(150.0).is_none());
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_lowering_priority() {
+        std::thread::spawn(|| {
+            lower_priority();
+            lower_priority();
+            assert_eq!(unsafe { libc::sched_getscheduler(0) }, libc::SCHED_IDLE);
+        }).join().unwrap();
+    }
+}
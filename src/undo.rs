@@ -0,0 +1,91 @@
+use crate::committer::REFLOG_PREFIX;
+use crate::errors::{RepositoryError, UndoError};
+use crate::lock::RepoLock;
+use failure::Error;
+use git2::{ErrorCode, Oid, Repository};
+use std::path::Path;
+
+/// How the reflog entries written by `undo` start, followed by the subject of the commit undone.
+pub const UNDO_REFLOG_PREFIX: &str = "undo (gitchain): ";
+
+/// A gitchain commit that has been undone.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Undone {
+    /// The commit that was undone.
+    pub commit: Oid,
+    /// The commit the branch is back at, or `None` if the undone commit was the first.
+    pub parent: Option<Oid>,
+    /// The branch that was moved back, or `None` if HEAD is detached.
+    pub branch: Option<String>,
+}
+
+/// Undoes the last commit gitchain made on the current branch of the repository at `repo`, found
+/// through its reflog entry.  The branch goes back to the commit's parent and the index to the
+/// commit's tree, so what was committed is staged again, and the work tree is left alone.  Refuses
+/// if other commits have been made on top of it since.
+pub fn undo(repo: &Path) -> Result<Undone, Error> {
+    let repo = Repository::open(repo)
+        .map_err(|_| RepositoryError::OpenError {})?;
+    repo.workdir().ok_or(RepositoryError::WorkdirRetrievalError {})?;
+    let _lock = RepoLock::acquire(repo.path())?;
+
+    let head = repo.find_reference("HEAD")
+        .map_err(|_| RepositoryError::OpenError {})?;
+    let branch = head.symbolic_target().map(str::to_string);
+    let name = branch.as_deref().unwrap_or("HEAD");
+    let current = repo.refname_to_id("HEAD")
+        .map_err(|_| UndoError::NothingToUndoError {})?;
+
+    let oid = last_commit(&repo, name)?.ok_or(UndoError::NothingToUndoError {})?;
+    if oid != current {
+        return Err(UndoError::CommittedOnTopError { commit: oid.to_string() }.into());
+    }
+
+    let commit = repo.find_commit(oid)
+        .map_err(|_| UndoError::NothingToUndoError {})?;
+    let tree = commit.tree()
+        .map_err(|_| RepositoryError::TreeWriteError {})?;
+    let parent = commit.parent_ids().next();
+    let subject = commit.message().and_then(|message| message.lines().next()).unwrap_or("");
+    let log_message = format!("{}{}", UNDO_REFLOG_PREFIX, subject);
+
+    let moved = match parent {
+        Some(parent) => repo.reference_matching(name, parent, true, oid, &log_message).map(|_| ()),
+        // Undoing the first commit leaves the branch unborn again.
+        None => repo.find_reference(name).and_then(|mut reference| reference.delete()),
+    };
+    moved.map_err(|error| match error.code() {
+        ErrorCode::Modified => UndoError::CommittedOnTopError { commit: oid.to_string() }.into(),
+        _ => Error::from(RepositoryError::RefUpdateError {}),
+    })?;
+
+    let mut index = repo.index()
+        .map_err(|_| RepositoryError::FindIndexError {})?;
+    index.read_tree(&tree)
+        .and_then(|_| index.write())
+        .map_err(|_| UndoError::IndexRestoreError {})?;
+
+    Ok(Undone { commit: oid, parent, branch })
+}
+
+/// The last commit gitchain made on the ref `name` that has not been undone yet, going back
+/// through its reflog.
+fn last_commit(repo: &Repository, name: &str) -> Result<Option<Oid>, Error> {
+    let reflog = repo.reflog(name)
+        .map_err(|_| UndoError::NothingToUndoError {})?;
+
+    let mut undone = 0;
+    for entry in reflog.iter() {
+        let message = entry.message().unwrap_or("");
+        if message.starts_with(UNDO_REFLOG_PREFIX) {
+            undone += 1;
+        } else if message.starts_with(REFLOG_PREFIX) {
+            if undone == 0 {
+                return Ok(Some(entry.id_new()));
+            }
+            undone -= 1;
+        }
+    }
+
+    Ok(None)
+}
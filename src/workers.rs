@@ -0,0 +1,147 @@
+use crate::errors::WorkerError;
+use failure::Error;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use std::thread;
+
+/// Environment variable that sets the number of mining threads when `--threads` isn't given.
+pub const THREADS_VAR: &str = "GITCHAIN_THREADS";
+
+/// How many worker threads a miner runs, and whether each is pinned to a core of its own.
+#[derive(Clone, Debug, Default)]
+pub struct Workers {
+    threads: Option<usize>,
+    pin: bool,
+}
+
+impl Workers {
+    /// Workers sized from `GITCHAIN_THREADS`, or else from the CPUs this process may use.
+    pub fn new() -> Workers {
+        Workers::default()
+    }
+
+    /// Runs exactly `threads` workers.
+    pub fn with_threads(mut self, threads: usize) -> Workers {
+        self.threads = Some(threads);
+        self
+    }
+
+    /// Pins each worker to a core of its own, so the scheduler doesn't move them around.
+    pub fn pinned(mut self) -> Workers {
+        self.pin = true;
+        self
+    }
+
+    /// The number of workers to run: the one asked for, or `GITCHAIN_THREADS`, or the number of
+    /// CPUs available to the process, capped by its cgroup's CPU quota.
+    pub fn threads(&self) -> Result<usize, Error> {
+        if let Some(threads) = self.threads {
+            return check_threads(threads);
+        }
+        if let Ok(value) = env::var(THREADS_VAR) {
+            return parse_threads(&value);
+        }
+
+        let available = thread::available_parallelism().map_or(1, |threads| threads.get());
+        Ok(cgroup_cpu_limit().map_or(available, |limit| limit.min(available)))
+    }
+
+    /// Builds a thread pool for a miner to run its workers on.
+    pub fn build_pool(&self) -> Result<Arc<ThreadPool>, Error> {
+        let threads = self.threads()?;
+        let mut builder = ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .thread_name(|index| format!("gitchain-worker-{}", index));
+
+        if self.pin {
+            let cores = core_affinity::get_core_ids().unwrap_or_default();
+            if !cores.is_empty() {
+                builder = builder.start_handler(move |index| {
+                    core_affinity::set_for_current(cores[index % cores.len()]);
+                });
+            }
+        }
+
+        let pool = builder.build().map_err(|_| WorkerError::PoolError { threads })?;
+        Ok(Arc::new(pool))
+    }
+}
+
+fn parse_threads(value: &str) -> Result<usize, Error> {
+    let threads = value.trim().parse::<usize>()
+        .map_err(|_| WorkerError::InvalidThreadsError { value: value.to_string() })?;
+    check_threads(threads)
+}
+
+fn check_threads(threads: usize) -> Result<usize, Error> {
+    if threads == 0 {
+        return Err(WorkerError::InvalidThreadsError { value: threads.to_string() }.into());
+    }
+    Ok(threads)
+}
+
+/// The number of CPUs the cgroup v2 quota of this process allows it, rounded up, if it has one.
+/// Every cgroup from the process's own up to the root can set a quota, and the smallest wins.
+pub fn cgroup_cpu_limit() -> Option<usize> {
+    let cgroups = fs::read_to_string("/proc/self/cgroup").ok()?;
+    let path = cgroups.lines().find_map(|line| line.strip_prefix("0::"))?;
+
+    let root = Path::new("/sys/fs/cgroup");
+    let mut dir = root.join(path.trim_start_matches('/'));
+    let mut limit: Option<usize> = None;
+    loop {
+        if let Some(quota) = fs::read_to_string(dir.join("cpu.max")).ok().and_then(|max| parse_cpu_max(&max)) {
+            limit = Some(limit.map_or(quota, |limit| limit.min(quota)));
+        }
+        if dir == root || !dir.pop() {
+            break;
+        }
+    }
+
+    limit
+}
+
+/// Parses the contents of a cgroup v2 `cpu.max` file, `<quota> <period>` or `max <period>`, into
+/// a whole number of CPUs.
+fn parse_cpu_max(contents: &str) -> Option<usize> {
+    let mut fields = contents.split_whitespace();
+    let quota = fields.next()?.parse::<u64>().ok()?;
+    let period = fields.next()?.parse::<u64>().ok().filter(|&period| period > 0)?;
+
+    Some((quota.div_ceil(period) as usize).max(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parsing_cpu_max() {
+        assert_eq!(parse_cpu_max("max 100000\n"), None);
+        assert_eq!(parse_cpu_max("200000 100000\n"), Some(2));
+        assert_eq!(parse_cpu_max("150000 100000\n"), Some(2));
+        assert_eq!(parse_cpu_max("5000 100000\n"), Some(1));
+        assert_eq!(parse_cpu_max(""), None);
+    }
+
+    #[test]
+    fn test_parsing_thread_counts() {
+        assert_eq!(parse_threads("4").unwrap(), 4);
+        assert_eq!(parse_threads(" 2\n").unwrap(), 2);
+        assert!(parse_threads("0").is_err());
+        assert!(parse_threads("all").is_err());
+        assert!(Workers::new().with_threads(0).threads().is_err());
+        assert_eq!(Workers::new().with_threads(3).threads().unwrap(), 3);
+    }
+
+    #[test]
+    fn test_pool_has_the_requested_threads() -> Result<(), Error> {
+        let pool = Workers::new().with_threads(3).pinned().build_pool()?;
+        assert_eq!(pool.current_num_threads(), 3);
+        assert!(Workers::new().threads()? >= 1);
+        Ok(())
+    }
+}
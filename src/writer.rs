@@ -1,4 +1,5 @@
 use failure::Error;
+use crate::dates;
 use crate::errors::WriterErrors;
 
 /// `generate_blob` associative method first checks to see if there is a parent, and if so it will
@@ -8,49 +9,61 @@ pub fn generate_blob(
     tree: String,
     parent: Option<String>,
     author: String,
+    committer: String,
     message: String,
-    commit_time: time::Tm,
+    author_time: time::Tm,
+    committer_time: time::Tm,
 ) -> Result<String, Error> {
-    let formatted_time = commit_time.strftime("%s %z")
-        .map_err(|_| WriterErrors::TimeFormatError {})?;
-
-    let time_str = format!("{}", formatted_time);
+    let author_time = format_time(author_time)?;
+    let committer_time = format_time(committer_time)?;
 
     let blob = if let Some(p) = parent {
-        generate_non_initial_blob(tree, p, author, message, time_str)
+        generate_non_initial_blob(tree, p, author, committer, message, author_time, committer_time)
     } else {
-        generate_initial_blob(tree, author, message, time_str)
+        generate_initial_blob(tree, author, committer, message, author_time, committer_time)
     };
 
     Ok(blob)
 }
 
+fn format_time(time: time::Tm) -> Result<String, Error> {
+    let seconds = dates::epoch_seconds(&time);
+    let offset = time.strftime("%z")
+        .map_err(|_| WriterErrors::TimeFormatError {})?;
+
+    Ok(format!("{} {}", seconds, offset))
+}
+
 fn generate_initial_blob(
     tree: String,
     author: String,
+    committer: String,
     message: String,
-    commit_time: String,
+    author_time: String,
+    committer_time: String,
 ) -> String {
-    return format!("tree {}\n\
+    format!("tree {}\n\
                        author {} {}\n\
                        committer {} {}\n\n\
                        {}",
                    tree,
                    author,
-                   commit_time,
-                   author,
-                   commit_time,
-                   message);
+                   author_time,
+                   committer,
+                   committer_time,
+                   message)
 }
 
 fn generate_non_initial_blob(
     tree: String,
     parent: String,
     author: String,
+    committer: String,
     message: String,
-    commit_time: String,
+    author_time: String,
+    committer_time: String,
 ) -> String {
-    return format!("tree {}\n\
+    format!("tree {}\n\
                        parent {}\n\
                        author {} {}\n\
                        committer {} {}\n\n\
@@ -58,16 +71,61 @@ fn generate_non_initial_blob(
                    tree,
                    parent,
                    author,
-                   commit_time,
-                   author,
-                   commit_time,
-                   message);
+                   author_time,
+                   committer,
+                   committer_time,
+                   message)
 }
 
+/// Number of hexadecimal digits the miner varies when searching for a nonce.  A nonce is 64 bits,
+/// but only its low 32 bits change from one attempt to the next; the high 32 bits are an extra
+/// nonce that is bumped whenever those run out.
+pub const NONCE_DIGITS: usize = 8;
+
 /// Appends a nonce to the end of the blob formatted as hexadecimal.  This is used to modify
 /// the blobs hash as we solve the Proof of Work.
-pub fn append_nonce_to_blob(blob: &str, nonce: u32) -> String {
-    format!("{}\n{:08x}", blob, nonce)
+pub fn append_nonce_to_blob(blob: &str, nonce: u64) -> String {
+    append_padded_nonce_to_blob(blob, nonce, NONCE_DIGITS)
+}
+
+/// Appends a nonce to the end of the blob formatted as hexadecimal, left padded with zeros so
+/// that it is at least `width` digits long.
+pub fn append_padded_nonce_to_blob(blob: &str, nonce: u64, width: usize) -> String {
+    format!("{}\n{:0width$x}", blob, nonce, width = width)
+}
+
+/// Number of digits a nonce needs so that none of its extra nonce is lost, which is how wide it
+/// has to be padded to at the very least.
+pub fn min_nonce_width(nonce: u64) -> usize {
+    match nonce >> 32 {
+        0 => NONCE_DIGITS,
+        extra => NONCE_DIGITS + format!("{:x}", extra).len(),
+    }
+}
+
+/// Writes a nonce as `NONCE_DIGITS` lowercase hexadecimal digits into the front of `buffer`,
+/// without allocating.  This matches the digits `append_nonce_to_blob` would produce.
+pub fn write_nonce_digits(buffer: &mut [u8], nonce: u32) {
+    const HEX: &[u8; 16] = b"0123456789abcdef";
+
+    for (i, digit) in buffer[..NONCE_DIGITS].iter_mut().enumerate() {
+        let shift = (NONCE_DIGITS - 1 - i) * 4;
+        *digit = HEX[(nonce >> shift) as usize & 0xf];
+    }
+}
+
+/// Returns the blob with the timestamps and timezones cut from its author and committer lines,
+/// so two blobs for the same commit made at different times compare equal.
+pub fn without_dates(blob: &str) -> String {
+    let (headers, message) = blob.split_at(blob.find("\n\n").unwrap_or(blob.len()));
+    let headers: Vec<&str> = headers.split('\n')
+        .map(|line| match line.rfind("> ") {
+            Some(end) if line.starts_with("author ") || line.starts_with("committer ") => &line[..end + 1],
+            _ => line,
+        })
+        .collect();
+
+    format!("{}{}", headers.join("\n"), message)
 }
 
 /// Prepends the necessary header to the blob, which is necessary before we check the blobs
@@ -86,25 +144,64 @@ mod tests {
         assert_eq!(blob, "test\n0000000f");
     }
 
+    #[test]
+    fn test_appending_padded_nonce() {
+        let blob = append_padded_nonce_to_blob("test", 15, 10);
+        assert_eq!(blob, "test\n000000000f");
+    }
+
+    #[test]
+    fn test_writing_nonce_digits() {
+        let mut buffer = *b"xxxxxxxxxx";
+        write_nonce_digits(&mut buffer[1..], 0xc0ffee);
+        assert_eq!(&buffer, b"x00c0ffeex");
+    }
+
+    #[test]
+    fn test_formatting_times_in_any_timezone() -> Result<(), Error> {
+        let mut time = time::at_utc(time::Timespec::new(1454691142 + 3600, 0));
+        time.tm_utcoff = 3600;
+        assert_eq!(format_time(time)?, "1454691142 +0100");
+
+        let now = time::now();
+        assert_eq!(format_time(now)?.split(' ').next(), Some(now.to_timespec().sec.to_string().as_str()));
+        Ok(())
+    }
+
     #[test]
     fn test_prepending_header() {
         let blob = prepend_header_to_blob("test");
         assert_eq!(blob, "commit 4\0test");
     }
 
+    #[test]
+    fn test_removing_dates() {
+        let blob = "tree TreeTest\n\
+                    author AuthorTest <test@test.com> 1454691142 -0000\n\
+                    committer AuthorTest <test@test.com> 1454691660 +0100\n\n\
+                    author MessageTest <in the message> 1";
+        let expected = "tree TreeTest\n\
+                        author AuthorTest <test@test.com>\n\
+                        committer AuthorTest <test@test.com>\n\n\
+                        author MessageTest <in the message> 1";
+        assert_eq!(without_dates(blob), expected);
+    }
+
     #[test]
     fn test_blob_generation_with_parent() -> Result<(), Error> {
         let tree = "TreeTest".to_string();
         let parent = Some("ParentTest".to_string());
         let author = "AuthorTest <test@test.com>".to_string();
         let message = "MessageTest".to_string();
-        let commit_time = time::strptime("2016-02-05 16:52:22", "%Y-%m-%d %H:%M:%S")?;
-        let blob = generate_blob(tree, parent, author, message, commit_time)?;
+        let author_time = time::strptime("2016-02-05 16:52:22", "%Y-%m-%d %H:%M:%S")?;
+        let committer_time = time::strptime("2016-02-05 17:01:00", "%Y-%m-%d %H:%M:%S")?;
+        let committer = "CommitterTest <committer@test.com>".to_string();
+        let blob = generate_blob(tree, parent, author, committer, message, author_time, committer_time)?;
 
         let expected = "tree TreeTest\n\
                                 parent ParentTest\n\
                                 author AuthorTest <test@test.com> 1454691142 -0000\n\
-                                committer AuthorTest <test@test.com> 1454691142 -0000\n\n\
+                                committer CommitterTest <committer@test.com> 1454691660 -0000\n\n\
                                 MessageTest";
         assert_eq!(blob, expected);
 
@@ -118,7 +215,7 @@ mod tests {
         let author = "AuthorTest <test@test.com>".to_string();
         let message = "MessageTest".to_string();
         let commit_time = time::strptime("2016-02-05 16:52:22", "%Y-%m-%d %H:%M:%S")?;
-        let blob = generate_blob(tree, parent, author, message, commit_time)?;
+        let blob = generate_blob(tree, parent, author.clone(), author, message, commit_time, commit_time)?;
 
         let expected = "tree TreeTest\n\
                                 author AuthorTest <test@test.com> 1454691142 -0000\n\
@@ -3,42 +3,384 @@ use std::path::{PathBuf, Path};
 use tempfile::TempDir;
 use std::error::Error;
 
-use gitchain::options::Opts;
+use gitchain::cancel::CancellationToken;
+use gitchain::clock::FixedClock;
+use gitchain::committer::Committer;
+use gitchain::identity::FixedIdentity;
+use gitchain::message::Message;
+use gitchain::options::Options;
+use gitchain::target::Target;
+use gitchain::undo;
+use gitchain::workers::Workers;
 use git2::Repository;
+use std::sync::Arc;
 
-fn generate_terminal_opts_for_commit(repo_path: PathBuf, prefix: &str) -> Opts {
-    Opts::Commit {
+#[test]
+fn test_commit() -> Result<(), Box<dyn Error>> {
+    let td = TempDir::new()?;
+    let repo = Repository::init(td.path())?;
+
+    let prefix = "0000";
+    let mut index = repo.index()?;
+    File::create(td.path().join("test.txt"))?;
+    index.add_path(Path::new("test.txt"))?;
+    index.write()?;
+
+    let mut options = generate_options_for_exact_commit(td.path().to_path_buf())?;
+    options.predicate = Box::new(Target::prefix(prefix)?);
+    let hash = Committer::new(options)?.commit()?;
+
+    assert_eq!(hash, "00002872230688efc3249437ba58ad4a6e1e8f14");
+    let head = repo.revparse_single("HEAD")?;
+    assert_eq!(head.id().to_string(), hash);
+    assert!(hash.starts_with(prefix));
+    Ok(())
+}
+
+fn generate_options_for_exact_commit(repo_path: PathBuf) -> Result<Options, Box<dyn Error>> {
+    Ok(Options {
         repo: repo_path,
-        msg: "Test Commit".to_string(),
-        prefix: prefix.to_string(),
-    }
+        msg: Message::Given("Test Commit".to_string()),
+        predicate: Box::new(Target::prefix("000")?),
+        backend: None,
+        nonce_style: None,
+        jitter: None,
+        observer: None,
+        clock: Some(Arc::new(FixedClock::at(1454691142, 3600))),
+        identity: Some(Arc::new(FixedIdentity::new("AuthorTest", "test@test.com"))),
+        cancel: CancellationToken::new(),
+        timeout: None,
+        resume: false,
+        retry: false,
+        no_verify: false,
+        within: None,
+        deterministic: true,
+        workers: Workers::new(),
+        cpu_budget: None,
+        background: false,
+        warn_after: None,
+    })
 }
 
 #[test]
-#[ignore]
-fn test_commit() -> Result<(), Box<dyn Error>> {
+fn test_exact_commit_with_fixed_clock_and_identity() -> Result<(), Box<dyn Error>> {
     let td = TempDir::new()?;
-    let td_path = td.path().to_path_buf();
     let repo = Repository::init(td.path())?;
 
-    let prefix = "000000";
-    let terminal_opts = generate_terminal_opts_for_commit(td_path.clone(), &prefix);
+    let mut index = repo.index()?;
+    File::create(td.path().join("test.txt"))?;
+    index.add_path(Path::new("test.txt"))?;
+    index.write()?;
+
+    let mut committer = Committer::new(generate_options_for_exact_commit(td.path().to_path_buf())?)?;
+    let hash = committer.commit()?;
+
+    assert_eq!(hash, "00074153929ea9f758a708171ce43d301c4bf5c4");
+
+    let head = repo.head()?.peel_to_commit()?;
+    assert_eq!(head.id().to_string(), hash);
+    assert_eq!(head.author().name(), Some("AuthorTest"));
+    assert_eq!(head.author().when().seconds(), 1454691142);
+    assert_eq!(head.author().when().offset_minutes(), 60);
+    assert_eq!(head.message(), Some("Test Commit\n00001292"));
+    Ok(())
+}
+
+/// Stages a file holding `contents` and commits it straight through git2, as another tool
+/// committing behind gitchain's back would.
+fn commit_behind_the_back(repo: &Repository, contents: &str) -> Result<git2::Oid, Box<dyn Error>> {
+    std::fs::write(repo.workdir().unwrap().join("other.txt"), contents)?;
+    let mut index = repo.index()?;
+    index.add_path(Path::new("other.txt"))?;
+    index.write()?;
+    let tree = repo.find_tree(index.write_tree()?)?;
+    let signature = git2::Signature::now("Other", "other@test.com")?;
+    let parent = repo.head()?.peel_to_commit()?;
+    Ok(repo.commit(Some("HEAD"), &signature, &signature, "Other", &tree, &[&parent])?)
+}
+
+fn repository_with_first_commit(td: &TempDir) -> Result<Repository, Box<dyn Error>> {
+    let repo = Repository::init(td.path())?;
+    let mut index = repo.index()?;
+    File::create(td.path().join("test.txt"))?;
+    index.add_path(Path::new("test.txt"))?;
+    index.write()?;
+    Committer::new(generate_options_for_exact_commit(td.path().to_path_buf())?)?.commit()?;
+    Ok(repo)
+}
+
+#[test]
+fn test_commit_leaves_work_tree_and_writes_reflog() -> Result<(), Box<dyn Error>> {
+    let td = TempDir::new()?;
+    let repo = repository_with_first_commit(&td)?;
+    std::fs::write(td.path().join("test.txt"), "unstaged work")?;
+
+    let mut options = generate_options_for_exact_commit(td.path().to_path_buf())?;
+    options.msg = Message::Given("Second Commit\n\nWith a body".to_string());
+    let hash = Committer::new(options)?.commit()?;
+
+    assert_eq!(std::fs::read_to_string(td.path().join("test.txt"))?, "unstaged work");
+    let reflog = repo.reflog("refs/heads/master")?;
+    let entry = reflog.get(0).unwrap();
+    assert_eq!(entry.id_new().to_string(), hash);
+    assert_eq!(entry.message(), Some("commit (gitchain): Second Commit"));
+    assert_eq!(repo.head()?.name(), Some("refs/heads/master"));
+    Ok(())
+}
+
+#[test]
+fn test_refuses_to_commit_when_head_moved() -> Result<(), Box<dyn Error>> {
+    let td = TempDir::new()?;
+    let repo = repository_with_first_commit(&td)?;
+
+    let mut committer = Committer::new(generate_options_for_exact_commit(td.path().to_path_buf())?)?;
+    let other = commit_behind_the_back(&repo, "moved")?;
+    let error = committer.commit().unwrap_err();
+
+    assert!(error.to_string().starts_with("HEAD moved while mining"), "{}", error);
+    assert_eq!(repo.head()?.target(), Some(other));
+    Ok(())
+}
+
+#[test]
+fn test_refuses_to_commit_when_index_changed() -> Result<(), Box<dyn Error>> {
+    let td = TempDir::new()?;
+    let repo = repository_with_first_commit(&td)?;
+    let head = repo.head()?.target();
+
+    let mut committer = Committer::new(generate_options_for_exact_commit(td.path().to_path_buf())?)?;
+    std::fs::write(td.path().join("test.txt"), "staged later")?;
+    let mut index = repo.index()?;
+    index.add_path(Path::new("test.txt"))?;
+    index.write()?;
+    let error = committer.commit().unwrap_err();
+
+    assert!(error.to_string().starts_with("The index changed while mining"), "{}", error);
+    assert_eq!(repo.head()?.target(), head);
+    Ok(())
+}
+
+#[test]
+fn test_retry_mines_again_on_top_of_moved_head() -> Result<(), Box<dyn Error>> {
+    let td = TempDir::new()?;
+    let repo = repository_with_first_commit(&td)?;
+
+    #[cfg(unix)]
+    write_hook(&repo.path().join("hooks"), "pre-commit", "echo pre-commit >> .git/hooks.log")?;
+
+    let mut options = generate_options_for_exact_commit(td.path().to_path_buf())?;
+    options.retry = true;
+    let mut committer = Committer::new(options)?;
+    let other = commit_behind_the_back(&repo, "moved")?;
+    let hash = committer.commit()?;
+
+    assert_eq!(committer.retries(), 1);
+    #[cfg(unix)]
+    assert_eq!(std::fs::read_to_string(repo.path().join("hooks.log"))?, "pre-commit\npre-commit\n");
+    assert!(hash.starts_with("000"));
+    let head = repo.head()?.peel_to_commit()?;
+    assert_eq!(head.id().to_string(), hash);
+    assert_eq!(head.parent_id(0)?, other);
+    Ok(())
+}
+
+#[test]
+fn test_one_committer_per_repository() -> Result<(), Box<dyn Error>> {
+    let td = TempDir::new()?;
+    let _repo = repository_with_first_commit(&td)?;
+
+    let committer = Committer::new(generate_options_for_exact_commit(td.path().to_path_buf())?)?;
+    let error = Committer::new(generate_options_for_exact_commit(td.path().to_path_buf())?).err().unwrap();
+    assert!(error.to_string().starts_with("Another gitchain is committing"), "{}", error);
 
-    {
-        let mut index = repo.index()?;
-        let filepath = &td.path().join("test.txt");
-        File::create(&filepath)?;
-        index.add_path(Path::new("test.txt"))?;
-        index.write().unwrap();
+    drop(committer);
+    Committer::new(generate_options_for_exact_commit(td.path().to_path_buf())?)?;
+    Ok(())
+}
+
+#[test]
+fn test_undo_restores_the_state_before_committing() -> Result<(), Box<dyn Error>> {
+    let td = TempDir::new()?;
+    let repo = repository_with_first_commit(&td)?;
+    let first = repo.head()?.target().unwrap();
+
+    std::fs::write(td.path().join("test.txt"), "staged")?;
+    let mut index = repo.index()?;
+    index.add_path(Path::new("test.txt"))?;
+    index.write()?;
+    let hash = Committer::new(generate_options_for_exact_commit(td.path().to_path_buf())?)?.commit()?;
+    std::fs::write(td.path().join("test.txt"), "unstaged work")?;
+
+    let undone = undo::undo(td.path())?;
+    assert_eq!(undone.commit.to_string(), hash);
+    assert_eq!(undone.parent, Some(first));
+    assert_eq!(repo.head()?.target(), Some(first));
+
+    let committed = repo.find_commit(undone.commit)?.tree_id();
+    let mut index = repo.index()?;
+    index.read(true)?;
+    assert_eq!(index.write_tree()?, committed);
+    assert_eq!(std::fs::read_to_string(td.path().join("test.txt"))?, "unstaged work");
+    assert_eq!(repo.reflog("refs/heads/master")?.get(0).unwrap().message(), Some("undo (gitchain): Test Commit"));
+
+    // The first commit was gitchain's too, so it is next.
+    assert_eq!(undo::undo(td.path())?.parent, None);
+    assert!(repo.head().is_err());
+    assert!(undo::undo(td.path()).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_undo_refuses_with_commits_on_top() -> Result<(), Box<dyn Error>> {
+    let td = TempDir::new()?;
+    let repo = repository_with_first_commit(&td)?;
+    let other = commit_behind_the_back(&repo, "on top")?;
+
+    let error = undo::undo(td.path()).unwrap_err();
+    assert!(error.to_string().starts_with("Commits have been made on top"), "{}", error);
+    assert_eq!(repo.head()?.target(), Some(other));
+    Ok(())
+}
+
+#[cfg(unix)]
+fn write_hook(dir: &Path, name: &str, script: &str) -> Result<(), Box<dyn Error>> {
+    use std::os::unix::fs::PermissionsExt;
+
+    std::fs::create_dir_all(dir)?;
+    let path = dir.join(name);
+    std::fs::write(&path, format!("#!/bin/sh\n{}\n", script))?;
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755))?;
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_commit_runs_hooks() -> Result<(), Box<dyn Error>> {
+    let td = TempDir::new()?;
+    let repo = repository_with_first_commit(&td)?;
+    let hooks = repo.path().join("hooks");
+    write_hook(&hooks, "pre-commit", "echo pre-commit >> .git/hooks.log")?;
+    write_hook(&hooks, "prepare-commit-msg", "echo prepare-commit-msg $2 >> .git/hooks.log")?;
+    write_hook(&hooks, "commit-msg", "echo commit-msg >> .git/hooks.log; printf '\\nSigned-off-by: Test\\n' >> \"$1\"")?;
+    write_hook(&hooks, "post-commit", "echo post-commit >> .git/hooks.log")?;
+
+    let hash = Committer::new(generate_options_for_exact_commit(td.path().to_path_buf())?)?.commit()?;
+
+    let log = std::fs::read_to_string(repo.path().join("hooks.log"))?;
+    assert_eq!(log, "pre-commit\nprepare-commit-msg message\ncommit-msg\npost-commit\n");
+    let message = repo.find_commit(git2::Oid::from_str(&hash)?)?.message().unwrap().to_string();
+    assert!(message.starts_with("Test Commit\n\nSigned-off-by: Test\n"), "{}", message);
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_rejecting_hook_stops_the_commit_unless_skipped() -> Result<(), Box<dyn Error>> {
+    let td = TempDir::new()?;
+    let repo = repository_with_first_commit(&td)?;
+    let head = repo.head()?.target();
+    repo.config()?.set_str("core.hooksPath", "githooks")?;
+    write_hook(&td.path().join("githooks"), "commit-msg", "exit 1")?;
+    write_hook(&td.path().join("githooks"), "prepare-commit-msg", "echo prepare-commit-msg >> .git/hooks.log")?;
+    write_hook(&td.path().join("githooks"), "post-commit", "echo post-commit >> .git/hooks.log")?;
+
+    let error = Committer::new(generate_options_for_exact_commit(td.path().to_path_buf())?).err().unwrap();
+    assert!(error.to_string().starts_with("The commit-msg hook rejected the commit"), "{}", error);
+    assert_eq!(repo.head()?.target(), head);
+
+    let mut options = generate_options_for_exact_commit(td.path().to_path_buf())?;
+    options.no_verify = true;
+    let hash = Committer::new(options)?.commit()?;
+    assert_eq!(repo.head()?.target().map(|oid| oid.to_string()), Some(hash));
+    let log = std::fs::read_to_string(repo.path().join("hooks.log"))?;
+    assert_eq!(log, "prepare-commit-msg\nprepare-commit-msg\npost-commit\n");
+    Ok(())
+}
+
+/// Runs the gitchain binary on the repository at `dir` with `args`, leaving out the editor
+/// variables of the environment the tests run in, so the repository's `core.editor` is used.
+#[cfg(unix)]
+fn run_gitchain_commit(dir: &Path, args: &[&str]) -> Result<std::process::Output, Box<dyn Error>> {
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_gitchain"))
+        .arg("commit")
+        .arg("--repository")
+        .arg(dir)
+        .args(args)
+        .env_remove("GIT_EDITOR")
+        .env_remove("VISUAL")
+        .env_remove("EDITOR")
+        .env("HOME", dir)
+        .stdin(std::process::Stdio::null())
+        .output()?;
+    Ok(output)
+}
+
+#[cfg(unix)]
+#[test]
+fn test_edited_message_is_committed() -> Result<(), Box<dyn Error>> {
+    let td = TempDir::new()?;
+    let repo = repository_with_first_commit(&td)?;
+    repo.config()?.set_str("user.name", "AuthorTest")?;
+    repo.config()?.set_str("user.email", "test@test.com")?;
+    std::fs::write(td.path().join("test.txt"), "staged")?;
+    let mut index = repo.index()?;
+    index.add_path(Path::new("test.txt"))?;
+    index.write()?;
+
+    let editor = td.path().join("editor.sh");
+    write_hook(td.path(), "editor.sh", "grep -q 'modified:   test.txt' \"$1\" && printf 'Edited subject  \\n\\n\\nBody\\n' | cat - \"$1\" > \"$1.new\" && mv \"$1.new\" \"$1\"")?;
+    repo.config()?.set_str("core.editor", &editor.display().to_string())?;
 
-        gitchain::run(terminal_opts)?;
-    }
+    let output = run_gitchain_commit(td.path(), &["--prefix", "0"])?;
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
 
-    let repository = Repository::open(td_path)?;
-    let head = repository.revparse_single("HEAD")?;
-    let head_id = format!("{}", head.id());
+    let head = repo.head()?.peel_to_commit()?;
+    let message = head.message().unwrap().to_string();
+    assert!(head.id().to_string().starts_with('0'));
+    assert!(message.starts_with("Edited subject\n\nBody\n"), "{}", message);
+    assert!(!message.contains('#'), "{}", message);
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_empty_or_unedited_message_aborts_before_mining() -> Result<(), Box<dyn Error>> {
+    let td = TempDir::new()?;
+    let repo = repository_with_first_commit(&td)?;
+    let head = repo.head()?.target();
+    repo.config()?.set_str("core.editor", "true")?;
+
+    let output = run_gitchain_commit(td.path(), &["--prefix", "0"])?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(stderr.starts_with("error: Aborting commit due to empty commit message"), "{}", stderr);
+
+    std::fs::write(td.path().join("template.txt"), "Subject\n\n# Explain why\n")?;
+    repo.config()?.set_str("commit.template", &td.path().join("template.txt").display().to_string())?;
+    let output = run_gitchain_commit(td.path(), &["--prefix", "0"])?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(stderr.starts_with("error: Aborting commit; you did not edit the message"), "{}", stderr);
+
+    let mut options = generate_options_for_exact_commit(td.path().to_path_buf())?;
+    options.msg = Message::Given(" \n\n".to_string());
+    assert!(Committer::new(options).is_err());
+    assert_eq!(repo.head()?.target(), head);
+    Ok(())
+}
+
+#[test]
+fn test_invalid_warn_after_config_is_ignored() -> Result<(), Box<dyn Error>> {
+    let td = TempDir::new()?;
+    let repo = repository_with_first_commit(&td)?;
+    repo.config()?.set_str("gitchain.warnAfter", "soon")?;
 
-     assert!(head_id.starts_with(&prefix));
+    let committer = Committer::new(generate_options_for_exact_commit(td.path().to_path_buf())?)?;
+    assert_eq!(committer.warn_after(), None);
+    drop(committer);
 
+    repo.config()?.set_str("gitchain.warnAfter", "10m")?;
+    let committer = Committer::new(generate_options_for_exact_commit(td.path().to_path_buf())?)?;
+    assert_eq!(committer.warn_after(), Some(std::time::Duration::from_secs(600)));
     Ok(())
 }